@@ -0,0 +1,147 @@
+//! Layouts of the exports of the supported brokers.
+//!
+//! The tax is computed from a canonical set of columns ([`Column`]). A
+//! [`BrokerFormat`] tells under which header names a broker exports each of them,
+//! which of its records are disposals, and how it formats dates. Other brokers can
+//! be supported by implementing the trait.
+
+use std::fmt::Debug;
+
+use time::{format_description::BorrowedFormatItem, macros::format_description};
+
+use crate::XLSX_DATE_FMT;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Column {
+    DateSold,
+    GainLoss,
+    RecordType,
+    TotalProceeds,
+    Symbol,
+    CostBasis,
+    Account,
+    TradeDate,
+    /// Commissions and fees of the disposal, deducted from its gain.
+    Fees,
+    /// Currency of the amounts of the row, when it may differ from the account's.
+    Currency,
+    /// Number of shares sold, read with [`Column::SalePrice`] and [`Column::CostBasis`]
+    /// when there is no [`Column::GainLoss`].
+    Quantity,
+    /// Price of each share sold.
+    SalePrice,
+}
+
+pub trait BrokerFormat: Debug + Send + Sync {
+    /// Header names the column may have in this broker's exports, empty if the broker
+    /// does not export it. Without a [`Column::RecordType`], every row is a disposal.
+    fn header_names(&self, column: Column) -> &'static [&'static str];
+
+    /// Whether a value of the record type column denotes a disposal. The supported
+    /// brokers ignore the case and the surrounding whitespace of the value.
+    fn is_sale_record(&self, record_type: &str) -> bool;
+
+    fn date_format(&self) -> &'static [BorrowedFormatItem<'static>];
+}
+
+/// ETrade's "Gains & Losses" export, the default layout.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ETrade;
+
+/// Record types realising a gain or a loss: regular sales, and the buying to cover that
+/// closes a short sale, with the gain of the round trip and negative proceeds. Opening
+/// the short sale realises nothing yet, so that the round trip is only counted once.
+const ETRADE_SALE_RECORD_TYPES: [&str; 2] = ["Sell", "Buy to Cover"];
+
+impl BrokerFormat for ETrade {
+    fn header_names(&self, column: Column) -> &'static [&'static str] {
+        match column {
+            Column::DateSold => &["Date Sold"],
+            Column::GainLoss => &["Adjusted Gain/Loss"],
+            Column::RecordType => &["Record Type"],
+            Column::TotalProceeds => &["Total Proceeds"],
+            Column::Symbol => &["Symbol", "Security"],
+            Column::CostBasis => &["Cost Basis", "Adjusted Cost Basis"],
+            Column::Account => &["Account", "Account Number"],
+            Column::TradeDate => &["Trade Date"],
+            Column::Fees => &["Commission", "Fees"],
+            Column::Currency => &["Currency"],
+            Column::Quantity => &["Quantity", "Qty."],
+            Column::SalePrice => &["Sale Price", "Proceeds Per Share"],
+        }
+    }
+
+    fn is_sale_record(&self, record_type: &str) -> bool {
+        let record_type = record_type.trim();
+        ETRADE_SALE_RECORD_TYPES
+            .iter()
+            .any(|sale| sale.eq_ignore_ascii_case(record_type))
+    }
+
+    fn date_format(&self) -> &'static [BorrowedFormatItem<'static>] {
+        XLSX_DATE_FMT
+    }
+}
+
+/// Interactive Brokers' "Trades" flex query, with the `TradeDate`, `Buy/Sell`,
+/// `Proceeds` and `FifoPnlRealized` fields.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InteractiveBrokers;
+
+impl BrokerFormat for InteractiveBrokers {
+    fn header_names(&self, column: Column) -> &'static [&'static str] {
+        match column {
+            Column::DateSold => &["TradeDate"],
+            Column::GainLoss => &["FifoPnlRealized"],
+            Column::RecordType => &["Buy/Sell"],
+            Column::TotalProceeds => &["Proceeds"],
+            Column::Symbol => &["Symbol"],
+            Column::Account => &["ClientAccountID"],
+            Column::Currency => &["CurrencyPrimary"],
+            Column::Quantity => &["Quantity"],
+            Column::SalePrice => &["TradePrice"],
+            // The cost basis of a sale is negative, the trade date is the only date, and
+            // the realised P/L is already net of the commission
+            Column::CostBasis | Column::TradeDate | Column::Fees => &[],
+        }
+    }
+
+    fn is_sale_record(&self, record_type: &str) -> bool {
+        record_type.trim().eq_ignore_ascii_case("SELL")
+    }
+
+    fn date_format(&self) -> &'static [BorrowedFormatItem<'static>] {
+        format_description!("[year][month][day]")
+    }
+}
+
+/// Degiro's realised gains/losses export, with one row per disposal.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Degiro;
+
+impl BrokerFormat for Degiro {
+    fn header_names(&self, column: Column) -> &'static [&'static str] {
+        match column {
+            Column::DateSold => &["Date"],
+            Column::GainLoss => &["Realised P/L"],
+            Column::TotalProceeds => &["Total"],
+            Column::Symbol => &["Product", "ISIN"],
+            Column::RecordType
+            | Column::CostBasis
+            | Column::Account
+            | Column::TradeDate
+            | Column::Fees
+            | Column::Currency
+            | Column::Quantity
+            | Column::SalePrice => &[],
+        }
+    }
+
+    fn is_sale_record(&self, _record_type: &str) -> bool {
+        true
+    }
+
+    fn date_format(&self) -> &'static [BorrowedFormatItem<'static>] {
+        format_description!("[day]-[month]-[year]")
+    }
+}
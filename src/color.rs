@@ -0,0 +1,72 @@
+//! Colours of the amounts of the text reports: gains in green, losses in red and the
+//! tax due in bold. Disabled by default, so that the output is plain unless the binary
+//! enables them for a terminal.
+
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use anstyle::{AnsiColor, Style};
+use rust_decimal::Decimal;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Enables or disables the colours of all the reports printed afterwards.
+pub fn set_colors(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+fn paint(style: Style, text: impl Display) -> String {
+    if ENABLED.load(Ordering::Relaxed) {
+        format!("{}{}{}", style.render(), text, style.render_reset())
+    } else {
+        text.to_string()
+    }
+}
+
+pub(crate) fn gain(text: impl Display) -> String {
+    paint(AnsiColor::Green.on_default(), text)
+}
+
+pub(crate) fn loss(text: impl Display) -> String {
+    paint(AnsiColor::Red.on_default(), text)
+}
+
+/// A gain or a loss depending on the sign of the amount, in the default colour if zero.
+pub(crate) fn net(amount: Decimal, text: impl Display) -> String {
+    if amount > Decimal::ZERO {
+        gain(text)
+    } else if amount < Decimal::ZERO {
+        loss(text)
+    } else {
+        text.to_string()
+    }
+}
+
+pub(crate) fn tax(text: impl Display) -> String {
+    paint(Style::new().bold(), text)
+}
+
+/// Colour of an amount, chosen before the amount is laid out.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Paint {
+    Plain,
+    Gain,
+    Loss,
+    /// See [`net`].
+    Net(Decimal),
+    Tax,
+}
+
+impl Paint {
+    pub(crate) fn apply(self, text: impl Display) -> String {
+        match self {
+            Paint::Plain => text.to_string(),
+            Paint::Gain => gain(text),
+            Paint::Loss => loss(text),
+            Paint::Net(amount) => net(amount, text),
+            Paint::Tax => tax(text),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Errors returned by the library, so that its users can tell the failure modes apart.
+
+use rust_decimal::Decimal;
+use time::Date;
+
+#[derive(Debug, thiserror::Error)]
+pub enum CgtError {
+    #[error(
+        "missing sheet {sheet:?}{} (available sheets: {})",
+        closest.as_ref().map(|s| format!(", did you mean {:?}?", s)).unwrap_or_default(),
+        available.join(", ")
+    )]
+    MissingSheet {
+        sheet: String,
+        /// Available sheet whose name is close to the missing one, e.g. a typo fix.
+        closest: Option<String>,
+        available: Vec<String>,
+    },
+    #[error("failed to find {0} header")]
+    MissingHeader(String),
+    #[error("all cells should be from the same fiscal year")]
+    MixedFiscalYears,
+    #[error("no disposals found")]
+    NoDisposals,
+    #[error(
+        "no disposals found in {year} (years of the disposals: {})",
+        available.iter().map(i32::to_string).collect::<Vec<_>>().join(", ")
+    )]
+    MissingYear { year: i32, available: Vec<i32> },
+    #[error("the period ends on {1}, before it starts on {0}")]
+    InvalidPeriod(Date, Date),
+    #[error("the period from {0} to {1} spans several fiscal years")]
+    PeriodAcrossYears(Date, Date),
+    #[error(
+        "missing entry from EXR CSV for {0} and the {days} previous days",
+        days = crate::EXR_FALLBACK_DAYS
+    )]
+    ExchangeRateUnavailable(Date),
+    #[error("the exchange rate {1} observed on {0} is not positive")]
+    InvalidExchangeRate(Date, Decimal),
+    #[error(
+        "missing exchange rate of {0} for {1} and the {days} previous days",
+        days = crate::EXR_FALLBACK_DAYS
+    )]
+    CurrencyRateUnavailable(String, Date),
+    #[error("the tax can only be computed in EUR, not in {0}")]
+    UnsupportedTargetCurrency(String),
+    #[error("the {operation} of {symbol} shares on {date} has a quantity of {quantity}, which is not positive")]
+    InvalidQuantity {
+        /// `acquisition` or `disposal`.
+        operation: &'static str,
+        symbol: String,
+        date: Date,
+        quantity: Decimal,
+    },
+    #[error("the dates of the file are in none of the supported formats")]
+    UnknownDateFormat,
+    #[error(
+        "ambiguous date format: {0:?} can be read in several formats, pass the format of the dates"
+    )]
+    AmbiguousDateFormat(String),
+    #[error("date {0:?} is in another format than expected, pass the format of the dates")]
+    WrongDateFormat(String),
+    #[error("missing yearly average exchange rate of {0}")]
+    YearlyRateUnavailable(i32),
+    #[error("EXR field {0:?} is not a valid positive decimal number")]
+    RateParse(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "io")]
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[cfg(feature = "io")]
+    #[error(transparent)]
+    Xlsx(#[from] calamine::XlsxError),
+    #[cfg(feature = "io")]
+    #[error(transparent)]
+    XlsxWrite(#[from] rust_xlsxwriter::XlsxError),
+    #[cfg(feature = "io")]
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error(transparent)]
+    DateParse(#[from] time::error::Parse),
+    #[error(transparent)]
+    DateFormat(#[from] time::error::Format),
+    #[error(transparent)]
+    DateRange(#[from] time::error::ComponentRange),
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+/// Keeps the variant of a [`CgtError`] that was given some context on its way up.
+impl From<anyhow::Error> for CgtError {
+    fn from(error: anyhow::Error) -> Self {
+        error.downcast().unwrap_or_else(CgtError::Other)
+    }
+}
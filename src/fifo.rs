@@ -0,0 +1,341 @@
+//! First-In-First-Out matching of disposals against acquisitions.
+//!
+//! Irish Revenue requires sold shares to be matched against the earliest acquired
+//! shares still held, whereas brokers let the seller pick which lot a sale applies
+//! to. The broker's "Adjusted Gain/Loss" column therefore cannot be trusted as-is:
+//! this module recomputes each disposal's gain from the Buy and Sell records.
+//! The cost of the matched shares is converted at the rates of their acquisition
+//! dates and the proceeds at the rate of the disposal date, so that the EUR gain
+//! accounts for the moves of the exchange rate while the shares were held.
+//!
+//! Disposals are matched in the order of the Irish share identification rules:
+//! first against shares of the same asset acquired on the same day, then against
+//! those acquired in the four weeks before it (the four-week rule), and only then
+//! against the oldest shares held. A loss is also disallowed when the same asset
+//! is reacquired within four weeks after it.
+
+use std::collections::HashMap;
+#[cfg(feature = "io")]
+use std::{collections::BTreeSet, path::Path};
+
+use anyhow::Context;
+#[cfg(feature = "io")]
+use calamine::{Data, DataType};
+use rust_decimal::Decimal;
+use time::{Date, Duration};
+
+#[cfg(feature = "io")]
+use crate::{
+    flag_transactions, open_sheet, parse_amount, parse_date_with, resolve_date_format,
+    ExchangeRateCache, ReadParams,
+};
+use crate::{CgtError, Eur, Result, Transaction};
+
+/// FIFO and broker gains diverging by more than this (in EUR) trigger a warning.
+const RECONCILIATION_TOLERANCE_EUR: Eur = Eur(Decimal::from_parts(1, 0, 0, false, 2));
+const FOUR_WEEKS: Duration = Duration::weeks(4);
+
+#[derive(Debug, Clone)]
+pub struct Acquisition {
+    pub symbol: String,
+    pub date: Date,
+    pub quantity: Decimal,
+    pub usd_cost: Decimal,
+}
+
+#[derive(Debug, Clone)]
+pub struct Disposal {
+    pub symbol: String,
+    pub date: Date,
+    pub quantity: Decimal,
+    pub usd_proceeds: Decimal,
+    /// Gain/loss as computed by the broker, only used for reconciliation.
+    pub usd_reported_gain_loss: Decimal,
+    /// Input file the disposal was read from, carried over to its transaction.
+    pub source_file: String,
+    pub account: String,
+}
+
+/// Matches every disposal against the shares of the same asset still held and
+/// returns the resulting transactions, in disposal date order.
+///
+/// Shares acquired on the day of a disposal are matched first, then those acquired
+/// within the four weeks preceding it (and the transaction is flagged accordingly),
+/// then the oldest shares held. `get_exr` provides the exchange rate to EUR for a
+/// given sale or acquisition date, along with the date of the observation it comes
+/// from. An acquisition or a disposal of no shares, or of a negative quantity, is
+/// rejected with [`CgtError::InvalidQuantity`].
+pub fn match_disposals<F>(
+    acquisitions: &[Acquisition],
+    disposals: &[Disposal],
+    mut get_exr: F,
+) -> Result<Vec<Transaction>>
+where
+    F: FnMut(Date) -> Result<(Decimal, Date)>,
+{
+    let mut lots: HashMap<String, Vec<Acquisition>> = HashMap::new();
+    for a in acquisitions {
+        // The cost of a lot is shared out by quantity
+        if a.quantity <= Decimal::ZERO {
+            return Err(CgtError::InvalidQuantity {
+                operation: "acquisition",
+                symbol: a.symbol.clone(),
+                date: a.date,
+                quantity: a.quantity,
+            });
+        }
+        lots.entry(a.symbol.clone()).or_default().push(a.clone());
+    }
+    lots.values_mut().for_each(|l| l.sort_by_key(|a| a.date));
+
+    // A disposal without shares would match no lot, and realise its proceeds as a gain
+    if let Some(d) = disposals.iter().find(|d| d.quantity <= Decimal::ZERO) {
+        return Err(CgtError::InvalidQuantity {
+            operation: "disposal",
+            symbol: d.symbol.clone(),
+            date: d.date,
+            quantity: d.quantity,
+        });
+    }
+    let mut disposals = disposals.to_vec();
+    disposals.sort_by_key(|d| d.date);
+
+    let mut transactions = Vec::with_capacity(disposals.len());
+    for d in disposals {
+        let symbol_lots = lots.entry(d.symbol.clone()).or_default();
+        let mut remaining = d.quantity;
+        let mut usd_cost = Decimal::ZERO;
+        let mut eur_cost = Eur::ZERO;
+        let mut four_week_rule_applied = false;
+        while remaining > Decimal::ZERO {
+            let same_day = symbol_lots.iter().position(|lot| lot.date == d.date);
+            let recent = symbol_lots
+                .iter()
+                .position(|lot| lot.date < d.date && lot.date >= d.date - FOUR_WEEKS);
+            let index = match (same_day, recent) {
+                (Some(index), _) => index,
+                (None, Some(index)) => {
+                    four_week_rule_applied |= index != 0;
+                    index
+                }
+                (None, None) => 0,
+            };
+            let lot = symbol_lots
+                .get_mut(index)
+                .filter(|lot| lot.date <= d.date)
+                .with_context(|| {
+                    format!(
+                        "disposal of {} {} shares on {} exceeds the shares held at that date",
+                        d.quantity, d.symbol, d.date
+                    )
+                })?;
+            let matched = Decimal::min(remaining, lot.quantity);
+            let matched_cost = lot.usd_cost * matched / lot.quantity;
+            let (acquisition_exr, _) =
+                get_exr(lot.date).context("failed to retrieve exchange rate")?;
+            usd_cost += matched_cost;
+            eur_cost += Eur::convert(matched_cost, acquisition_exr);
+            lot.usd_cost -= matched_cost;
+            lot.quantity -= matched;
+            remaining -= matched;
+            if lot.quantity.is_zero() {
+                symbol_lots.remove(index);
+            }
+        }
+
+        let exr = get_exr(d.date).context("failed to retrieve exchange rate")?;
+        let usd_gain_loss = d.usd_proceeds - usd_cost;
+        let mut t = Transaction::from_usd(d.date, usd_gain_loss, d.usd_proceeds, exr);
+        let eur_gain_loss = t.eur_proceeds - eur_cost;
+        t.eur_gain = eur_gain_loss.max(Eur::ZERO);
+        t.eur_loss = (-eur_gain_loss).max(Eur::ZERO);
+        t.symbol = d.symbol.clone();
+        t.source_file = d.source_file.clone();
+        t.account = d.account.clone();
+        t.four_week_rule_applied = four_week_rule_applied;
+        // The broker's gain is in the account currency, so compare the gains before
+        // the cost is converted at the acquisition rates
+        let fifo_eur_gain_loss = Eur::convert(usd_gain_loss, t.exr);
+        let reported_eur_gain_loss = Eur::convert(d.usd_reported_gain_loss, t.exr);
+        if (fifo_eur_gain_loss - reported_eur_gain_loss).abs() > RECONCILIATION_TOLERANCE_EUR {
+            eprintln!(
+                "Warning: FIFO gain/loss for the disposal on {} (€{:.2}) differs from the spreadsheet (€{:.2})",
+                d.date, fifo_eur_gain_loss, reported_eur_gain_loss
+            );
+        }
+        transactions.push(t);
+    }
+    Ok(transactions)
+}
+
+/// Disallows the loss of every disposal followed, within four weeks, by the
+/// acquisition of the same asset. Adjusted transactions are flagged so that the
+/// CSV detail shows which rows were affected.
+pub fn apply_four_week_rule(transactions: &mut [Transaction], acquisitions: &[Acquisition]) {
+    for t in transactions
+        .iter_mut()
+        // The loss in EUR is the one taxed, even when the shares made a gain in USD
+        .filter(|t| t.eur_loss > Eur::ZERO)
+    {
+        let reacquired = acquisitions.iter().any(|a| {
+            a.symbol == t.symbol && a.date > t.sell_date && a.date <= t.sell_date + FOUR_WEEKS
+        });
+        if reacquired {
+            t.usd_loss = Decimal::ZERO;
+            t.eur_loss = Eur::ZERO;
+            t.four_week_rule_applied = true;
+        }
+    }
+}
+
+#[cfg(feature = "io")]
+struct FifoColumns {
+    record_type: usize,
+    quantity: usize,
+    date_acquired: usize,
+    acquisition_cost: usize,
+    date_sold: usize,
+    total_proceeds: usize,
+    gain_loss: usize,
+    symbol: Option<usize>,
+    account: Option<usize>,
+    trade_date: Option<usize>,
+}
+
+#[cfg(feature = "io")]
+fn get_fifo_column_indices(headers: Vec<String>) -> Result<FifoColumns> {
+    let find = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.trim() == name)
+            .ok_or_else(|| CgtError::MissingHeader(name.to_string()))
+    };
+    Ok(FifoColumns {
+        record_type: find("Record Type")?,
+        quantity: find("Quantity")?,
+        date_acquired: find("Date Acquired")?,
+        acquisition_cost: find("Acquisition Cost")?,
+        date_sold: find("Date Sold")?,
+        total_proceeds: find("Total Proceeds")?,
+        gain_loss: find("Adjusted Gain/Loss")?,
+        symbol: find("Symbol").or_else(|_| find("Security")).ok(),
+        account: find("Account").or_else(|_| find("Account Number")).ok(),
+        trade_date: find("Trade Date").ok(),
+    })
+}
+
+#[cfg(feature = "io")]
+/// Reads the Buy and Sell records of the spreadsheet and recomputes the gain of
+/// each sale with [`match_disposals`] and [`apply_four_week_rule`]. Shares may have
+/// been bought in earlier years, and the sales may span several fiscal years.
+pub fn get_transactions_fifo<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    get_transactions_fifo_from_files(&[file_path], params)
+}
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions_fifo`] for several spreadsheets (e.g. one per
+/// brokerage account). Shares of the same asset are matched across all of them,
+/// as Revenue considers the holdings of a person as a whole.
+pub fn get_transactions_fifo_from_files<P: AsRef<Path>>(
+    file_paths: &[P],
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    let mut acquisitions = Vec::new();
+    let mut disposals = Vec::new();
+    for file_path in file_paths {
+        read_fifo_records(file_path, params, &mut acquisitions, &mut disposals)?;
+    }
+
+    let mut exr_cache = ExchangeRateCache::from_params(params)?;
+    let currency = &params.from_currency;
+    let dates: BTreeSet<(String, Date)> = (disposals.iter().map(|d| d.date))
+        .chain(acquisitions.iter().map(|a| a.date))
+        .map(|date| (currency.clone(), date))
+        .collect();
+    exr_cache
+        .prefetch(&dates)
+        .context("failed to retrieve exchange rates")?;
+    let mut transactions = match_disposals(&acquisitions, &disposals, |date| {
+        exr_cache.get_exr(currency, date)
+    })?;
+    for t in &mut transactions {
+        t.currency = currency.clone();
+        t.target_currency = params.to_currency.clone();
+        t.exr_series = exr_cache.exr_source(currency, t.sell_date);
+        if params.round_to_cents {
+            t.round_to_cents(params.round_mode);
+        }
+    }
+    exr_cache.warn_anomalous_rates();
+    exr_cache.print_fetch_summary(transactions.len(), &dates);
+    apply_four_week_rule(&mut transactions, &acquisitions);
+    flag_transactions(&mut transactions, params);
+    Ok(transactions)
+}
+
+#[cfg(feature = "io")]
+fn read_fifo_records<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+    acquisitions: &mut Vec<Acquisition>,
+    disposals: &mut Vec<Disposal>,
+) -> Result<()> {
+    let source_file = file_path.as_ref().display().to_string();
+    let range = open_sheet(file_path, &params.sheet)?;
+    let headers = range.headers().context("failed to extract headers")?;
+    let cols = get_fifo_column_indices(headers)?;
+    let trade_date = cols.trade_date.filter(|_| params.use_trade_date);
+    let is_record = |r: &[Data], record_type: &str| {
+        r[cols.record_type]
+            .get_string()
+            .is_some_and(|s| s.trim().eq_ignore_ascii_case(record_type))
+    };
+    let date_cell = |r: &[Data]| -> usize {
+        if is_record(r, "Buy") {
+            cols.date_acquired
+        } else {
+            trade_date
+                .filter(|&i| !r[i].is_empty())
+                .unwrap_or(cols.date_sold)
+        }
+    };
+    let dates: Vec<String> = range
+        .rows()
+        .skip(1)
+        .filter(|r| is_record(r, "Buy") || is_record(r, "Sell"))
+        .filter_map(|r| r[date_cell(r)].get_string().map(str::to_string))
+        .collect();
+    let date_format = resolve_date_format(params, &dates)?;
+
+    for r in range.rows().skip(1) {
+        let symbol = cols
+            .symbol
+            .and_then(|i| r[i].as_string())
+            .unwrap_or_default();
+        if is_record(r, "Buy") {
+            acquisitions.push(Acquisition {
+                symbol,
+                date: parse_date_with(&r[date_cell(r)], date_format)?,
+                quantity: parse_amount(&r[cols.quantity], "quantity")?,
+                usd_cost: parse_amount(&r[cols.acquisition_cost], "acquisition cost")?,
+            })
+        } else if is_record(r, "Sell") {
+            disposals.push(Disposal {
+                symbol,
+                date: parse_date_with(&r[date_cell(r)], date_format)?,
+                quantity: parse_amount(&r[cols.quantity], "quantity")?,
+                usd_proceeds: parse_amount(&r[cols.total_proceeds], "total proceeds")?,
+                usd_reported_gain_loss: parse_amount(&r[cols.gain_loss], "gain/loss")?,
+                source_file: source_file.clone(),
+                account: cols
+                    .account
+                    .map(|i| r[i].to_string().trim().to_string())
+                    .unwrap_or_default(),
+            })
+        }
+    }
+    Ok(())
+}
@@ -1,324 +1,3732 @@
-use std::{collections::HashMap, path::Path};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+#[cfg(feature = "io")]
+use std::{
+    fs::File,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+};
 
+pub mod broker;
+mod color;
+mod error;
+pub mod fifo;
+mod money;
+
+pub use color::set_colors;
+pub use error::CgtError;
+pub use money::{format_money, Eur, RoundMode};
+
+#[cfg(feature = "io")]
 use anyhow::{Context, Error};
-use calamine::{open_workbook, Data, DataType, Reader, Xlsx};
-use time::{format_description::BorrowedFormatItem, macros::format_description, Date, Month};
+#[cfg(feature = "io")]
+use broker::Column;
+use broker::{BrokerFormat, ETrade};
+#[cfg(feature = "io")]
+use calamine::{open_workbook, Data, DataType, Range, Reader, Xlsx};
+use color::Paint;
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+#[cfg(feature = "io")]
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use time::{
+    format_description::BorrowedFormatItem,
+    macros::{date, format_description},
+    Date, Month,
+};
 
-pub type Result<T> = std::result::Result<T, Error>;
+pub type Result<T> = std::result::Result<T, CgtError>;
 
 const FROM_CURRENCY: &str = "USD";
 const TO_CURRENCY: &str = "EUR";
-const TAX_RATE: f64 = 0.33;
-const EXEMPTION_EUR: f64 = 1270.0;
+const TAX_RATE: Decimal = Decimal::from_parts(33, 0, 0, false, 2);
+const EXEMPTION_EUR: Eur = Eur(Decimal::from_parts(1270, 0, 0, false, 0));
+/// Statutory CGT rate and annual exemption of the disposals from the given date on,
+/// latest first. The rate changed during 2008, 2009, 2011 and 2012.
+const STATUTORY_RATES: [(Date, Decimal, Eur); 5] = [
+    (date!(2012 - 12 - 06), TAX_RATE, EXEMPTION_EUR),
+    (
+        date!(2011 - 12 - 07),
+        Decimal::from_parts(30, 0, 0, false, 2),
+        EXEMPTION_EUR,
+    ),
+    (
+        date!(2009 - 04 - 08),
+        Decimal::from_parts(25, 0, 0, false, 2),
+        EXEMPTION_EUR,
+    ),
+    (
+        date!(2008 - 10 - 15),
+        Decimal::from_parts(22, 0, 0, false, 2),
+        EXEMPTION_EUR,
+    ),
+    (
+        Date::MIN,
+        Decimal::from_parts(20, 0, 0, false, 2),
+        EXEMPTION_EUR,
+    ),
+];
+/// Total proceeds of the year above which a CGT return is required, even without tax due.
+const RETURN_THRESHOLD_EUR: Eur = Eur(Decimal::from_parts(2540, 0, 0, false, 0));
+const SHEET_NAME: &str = "G&L_Expanded";
+const MAX_RETRIES: u32 = 3;
+/// Requests to the ECB sent at the same time at most, so as not to overload it.
+const FETCH_JOBS: usize = 8;
+#[cfg(feature = "io")]
+const RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+#[cfg(feature = "io")]
+const EXR_BASE_URL: &str = "https://data-api.ecb.europa.eu/service/data/EXR";
+/// Environment variable overriding [`EXR_BASE_URL`] when the parameters do not.
+#[cfg(feature = "io")]
+const EXR_BASE_URL_VAR: &str = "CGT_EXR_BASE_URL";
+/// How far back to look for a rate when there is no observation for the sale date.
+const EXR_FALLBACK_DAYS: i64 = 7;
+/// Relative deviation from the yearly median above which a rate is reported as anomalous.
+const EXR_ANOMALY_THRESHOLD: Decimal = Decimal::from_parts(10, 0, 0, false, 2);
+/// Rows whose proceeds minus cost basis differ from their gain/loss by more than this
+/// (in the spreadsheet currency) trigger a warning.
+const COST_BASIS_TOLERANCE: Decimal = Decimal::from_parts(1, 0, 0, false, 2);
 
 static XLSX_DATE_FMT: &[BorrowedFormatItem] = format_description!("[month]/[day]/[year]");
+#[cfg(feature = "io")]
+static DAY_MONTH_YEAR_DATE_FMT: &[BorrowedFormatItem] = format_description!("[day]/[month]/[year]");
+#[cfg(feature = "io")]
+static ISO_DATE_FMT: &[BorrowedFormatItem] = format_description!("[year]-[month]-[day]");
 static EXR_API_DATE_FMT: &[BorrowedFormatItem] = format_description!("[year]-[month]-[day]");
+static DEADLINE_DATE_FMT: &[BorrowedFormatItem] =
+    format_description!("[day padding:none] [month repr:short] [year]");
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct Transaction {
     sell_date: Date,
-    usd_gain: f64,
-    usd_loss: f64,
-    eur_gain: f64,
-    eur_loss: f64,
-    exr: f64,
-    usd_proceeds: f64,
-    eur_proceeds: f64,
+    usd_gain: Decimal,
+    usd_loss: Decimal,
+    eur_gain: Eur,
+    eur_loss: Eur,
+    exr: Decimal,
+    usd_proceeds: Decimal,
+    eur_proceeds: Eur,
+    /// Commissions and fees already deducted from the gain (or added to the loss).
+    usd_fees: Decimal,
+    eur_fees: Eur,
+    /// Date of the exchange rate observation, before `sell_date` when there was none
+    /// for that day (e.g. weekends and bank holidays).
+    exr_date: Date,
+    /// ECB series (or rates file) the exchange rate comes from.
+    exr_series: String,
+    symbol: String,
+    four_week_rule_applied: bool,
+    /// Currency the `usd_*` amounts are actually denominated in.
+    currency: String,
+    /// Currency the `eur_*` amounts are actually denominated in.
+    target_currency: String,
+    /// Input file the transaction was read from.
+    source_file: String,
+    account: String,
+    /// Excluded transactions are kept in the CSV detail but left out of the reports.
+    excluded: bool,
+    /// Disposal of development land, taxed separately from the other gains.
+    development_land: bool,
 }
 
-#[derive(Debug, Default)]
+impl Transaction {
+    fn from_usd(
+        sell_date: Date,
+        usd_gain_loss: Decimal,
+        usd_proceeds: Decimal,
+        (exr, exr_date): (Decimal, Date),
+    ) -> Self {
+        let (usd_gain, usd_loss) = if usd_gain_loss >= Decimal::ZERO {
+            (usd_gain_loss, Decimal::ZERO)
+        } else {
+            (Decimal::ZERO, -usd_gain_loss)
+        };
+        Self {
+            sell_date,
+            usd_gain,
+            usd_loss,
+            eur_gain: Eur::convert(usd_gain, exr),
+            eur_loss: Eur::convert(usd_loss, exr),
+            exr,
+            usd_proceeds,
+            eur_proceeds: Eur::convert(usd_proceeds, exr),
+            usd_fees: Decimal::ZERO,
+            eur_fees: Eur::ZERO,
+            exr_date,
+            exr_series: String::new(),
+            symbol: String::new(),
+            four_week_rule_applied: false,
+            currency: FROM_CURRENCY.to_string(),
+            target_currency: TO_CURRENCY.to_string(),
+            source_file: String::new(),
+            account: String::new(),
+            excluded: false,
+            development_land: false,
+        }
+    }
+
+    /// Builds a disposal of the given proceeds realising `usd_gain_loss` (negative for a
+    /// loss), both in the currency of the account, converted at `exr` units of that
+    /// currency per euro.
+    ///
+    /// # Panics
+    ///
+    /// If `exr` is not positive, see [`Eur::convert`].
+    pub fn new(
+        sell_date: Date,
+        usd_gain_loss: Decimal,
+        usd_proceeds: Decimal,
+        exr: Decimal,
+    ) -> Self {
+        Self::from_usd(sell_date, usd_gain_loss, usd_proceeds, (exr, sell_date))
+    }
+
+    /// Marks the disposal as one of development land, taxed at its own rate.
+    pub fn with_development_land(mut self, development_land: bool) -> Self {
+        self.development_land = development_land;
+        self
+    }
+
+    pub fn sell_date(&self) -> Date {
+        self.sell_date
+    }
+
+    /// Gain in the currency of the account, zero for a loss.
+    pub fn usd_gain(&self) -> Decimal {
+        self.usd_gain
+    }
+
+    /// Loss in the currency of the account, as a positive amount, zero for a gain.
+    pub fn usd_loss(&self) -> Decimal {
+        self.usd_loss
+    }
+
+    pub fn eur_gain(&self) -> Eur {
+        self.eur_gain
+    }
+
+    pub fn eur_loss(&self) -> Eur {
+        self.eur_loss
+    }
+
+    /// Units of the currency of the account per euro.
+    pub fn exr(&self) -> Decimal {
+        self.exr
+    }
+
+    pub fn usd_proceeds(&self) -> Decimal {
+        self.usd_proceeds
+    }
+
+    pub fn eur_proceeds(&self) -> Eur {
+        self.eur_proceeds
+    }
+
+    pub fn usd_fees(&self) -> Decimal {
+        self.usd_fees
+    }
+
+    pub fn eur_fees(&self) -> Eur {
+        self.eur_fees
+    }
+
+    pub fn exr_date(&self) -> Date {
+        self.exr_date
+    }
+
+    pub fn exr_series(&self) -> &str {
+        &self.exr_series
+    }
+
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    pub fn four_week_rule_applied(&self) -> bool {
+        self.four_week_rule_applied
+    }
+
+    pub fn currency(&self) -> &str {
+        &self.currency
+    }
+
+    pub fn target_currency(&self) -> &str {
+        &self.target_currency
+    }
+
+    pub fn source_file(&self) -> &str {
+        &self.source_file
+    }
+
+    pub fn account(&self) -> &str {
+        &self.account
+    }
+
+    pub fn excluded(&self) -> bool {
+        self.excluded
+    }
+
+    pub fn development_land(&self) -> bool {
+        self.development_land
+    }
+
+    /// Rounds the EUR amounts to the cent, as Revenue expects per-transaction amounts.
+    fn round_to_cents(&mut self, mode: RoundMode) {
+        self.eur_gain = self.eur_gain.round_to_cents_with(mode);
+        self.eur_loss = self.eur_loss.round_to_cents_with(mode);
+        self.eur_proceeds = self.eur_proceeds.round_to_cents_with(mode);
+        self.eur_fees = self.eur_fees.round_to_cents_with(mode);
+    }
+}
+
+fn round_to_cents(amount: Decimal) -> Decimal {
+    amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct PeriodTaxReport {
-    pub usd_gain: f64,
-    pub usd_loss: f64,
-    pub usd_net_gain: f64,
-    pub eur_gain: f64,
-    pub eur_loss: f64,
-    pub eur_net_gain: f64,
-    pub usd_proceeds: f64,
-    pub eur_proceeds: f64,
-    pub eur_chargeable_gain: f64,
-    pub eur_tax: f64,
+    /// First and last day of the period, `None` for the entire fiscal year.
+    pub period: Option<(Date, Date)>,
+    /// Deadline to pay the tax of the period, `None` for the entire fiscal year.
+    pub due_date: Option<Date>,
+    /// Name of the payment period, `None` for the entire fiscal year.
+    pub label: Option<String>,
+    /// Number of disposals of the period, excluded transactions aside.
+    pub transaction_count: usize,
+    pub usd_gain: Decimal,
+    pub usd_loss: Decimal,
+    pub usd_net_gain: Decimal,
+    pub eur_gain: Eur,
+    pub eur_loss: Eur,
+    pub eur_net_gain: Eur,
+    pub usd_proceeds: Decimal,
+    pub eur_proceeds: Eur,
+    /// Part of the exemption (and of the losses carried forward) allocated to the period.
+    pub eur_allowance: Eur,
+    /// Net gain above `eur_allowance`.
+    pub eur_taxable_gain: Eur,
+    pub eur_tax: Eur,
+    /// Parts of `eur_taxable_gain` and `eur_tax` coming from disposals of development
+    /// land, taxed at their own rate.
+    pub eur_development_land_taxable_gain: Eur,
+    pub eur_development_land_tax: Eur,
 }
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Copy)]
+pub struct TaxParams {
+    pub rate: Decimal,
+    pub exemption_eur: Eur,
+    /// Part of the exemption already used by gains taxed outside the report (e.g. the
+    /// sale of a property), deducted from it.
+    pub exemption_used_eur: Eur,
+    /// Unused losses from prior years, deducted from the net gain before the exemption.
+    pub loss_carried_forward_eur: Eur,
+    /// Total proceeds of the year above which a return must be filed.
+    pub return_threshold_eur: Eur,
+    /// Rate of the gains on disposals of development land.
+    pub development_land_rate: Decimal,
+}
+
+impl Default for TaxParams {
+    fn default() -> Self {
+        Self {
+            rate: TAX_RATE,
+            exemption_eur: EXEMPTION_EUR,
+            exemption_used_eur: Eur::ZERO,
+            loss_carried_forward_eur: Eur::ZERO,
+            return_threshold_eur: RETURN_THRESHOLD_EUR,
+            development_land_rate: TAX_RATE,
+        }
+    }
+}
+
+impl TaxParams {
+    /// Default parameters of a disposal on the date, with the statutory rate and
+    /// exemption in force on that date (20% up to October 14th 2008, 22% up to April
+    /// 7th 2009, 25% up to December 6th 2011, 30% up to December 5th 2012 and 33%
+    /// since) rather than the current ones. Disposals of development land are taxed at
+    /// the same rate.
+    pub fn for_date(date: Date) -> Self {
+        let (_, rate, exemption_eur) = (STATUTORY_RATES.iter())
+            .find(|(from, ..)| date >= *from)
+            .copied()
+            .unwrap_or(STATUTORY_RATES[STATUTORY_RATES.len() - 1]);
+        Self {
+            rate,
+            exemption_eur,
+            development_land_rate: rate,
+            ..Self::default()
+        }
+    }
+
+    /// Default parameters of the fiscal year, with the statutory rate in force for most
+    /// of it (that of July 1st), see [`TaxParams::for_date`].
+    pub fn for_year(fiscal_year: i32) -> Self {
+        Self::for_date(Date::from_calendar_date(fiscal_year, Month::July, 1).unwrap_or(Date::MIN))
+    }
+
+    /// Default parameters of the disposals of the fiscal year, with the statutory rate
+    /// in force on their dates. When it changed between them (see
+    /// [`statutory_rate_change`]), they are those of the year.
+    pub fn for_disposals(fiscal_year: i32, transactions: &[Transaction]) -> Self {
+        match (
+            disposal_date_range(transactions),
+            statutory_rate_change(transactions),
+        ) {
+            (Some((first, _)), None) => Self::for_date(first),
+            _ => Self::for_year(fiscal_year),
+        }
+    }
+
+    /// Losses carried forward and exemption left, deducted in this order from the
+    /// chargeable gain. Only the exemption can be deducted from gains on development
+    /// land.
+    fn reliefs(&self) -> (Eur, Eur) {
+        (self.loss_carried_forward_eur, self.remaining_exemption())
+    }
+
+    /// Exemption left once the part already used is deducted, never negative.
+    fn remaining_exemption(&self) -> Eur {
+        (self.exemption_eur - self.exemption_used_eur)
+            .min(self.exemption_eur)
+            .max(Eur::ZERO)
+    }
+}
+
+/// Date on which the statutory rate changed between the first and the last of the
+/// disposals, leaving out the excluded ones, if it did. The disposals are then taxed at
+/// a single rate, while each should be at the rate in force on its date.
+pub fn statutory_rate_change(transactions: &[Transaction]) -> Option<Date> {
+    let (first, last) = disposal_date_range(transactions)?;
+    (STATUTORY_RATES.iter())
+        .map(|(from, ..)| *from)
+        .find(|from| first < *from && *from <= last)
+}
+
+fn disposal_date_range(transactions: &[Transaction]) -> Option<(Date, Date)> {
+    let mut dates = transactions
+        .iter()
+        .filter(|t| !t.excluded)
+        .map(|t| t.sell_date);
+    let first = dates.next()?;
+    Some(dates.fold((first, first), |(first, last), date| {
+        (first.min(date), last.max(date))
+    }))
+}
+
+/// Parameters for reading the transactions and converting them to the filing currency.
+#[derive(Debug, Clone)]
+pub struct ReadParams {
+    /// Currency the spreadsheet amounts are denominated in.
+    pub from_currency: String,
+    /// Currency the tax is computed in, which can only be EUR as the tax parameters are
+    /// in euros.
+    pub to_currency: String,
+    /// Number of times a failed exchange rate request is retried, with exponential backoff.
+    pub max_retries: u32,
+    /// Maximum number of requests to the ECB sent concurrently, one per year of sales.
+    /// 1 sends them one after the other.
+    pub fetch_jobs: usize,
+    /// Timeout of the connection to the ECB, and of each of its responses.
+    pub timeout: Duration,
+    /// URL of the EXR dataset of the ECB API, e.g. to query a proxy or a mock server.
+    /// Defaults to the `CGT_EXR_BASE_URL` environment variable, then to the ECB's.
+    pub exr_base_url: Option<String>,
+    /// Worksheet to read from XLSX files. Ignored if the workbook has a single sheet.
+    pub sheet: String,
+    /// Round the EUR amounts of each transaction to the cent.
+    pub round_to_cents: bool,
+    /// How the EUR amounts of each transaction are rounded to the cent.
+    pub round_mode: RoundMode,
+    /// Warn about exchange rates deviating from the median rate of their year by more
+    /// than this ratio. `None` disables the check.
+    pub exr_anomaly_threshold: Option<Decimal>,
+    /// Print every exchange rate lookup and a summary of the API calls.
+    pub verbose: bool,
+    /// CSV file (with `date,rate` columns) whose rates are used instead of the ECB's.
+    pub rates_file: Option<PathBuf>,
+    /// Fetch the rates of all dates, including those of the rates file, and use the ECB's
+    /// rate where they differ.
+    pub refresh_rates: bool,
+    /// CSV file every observation fetched from the ECB is appended to, along with the
+    /// URL it was fetched from, as a record of the rates the conversions rely on.
+    pub audit_log: Option<PathBuf>,
+    /// Warn about the rows whose gain/loss does not match their proceeds minus their
+    /// cost basis, when the file has a cost basis column.
+    pub check_cost_basis: bool,
+    /// Remove the sales with the same date, symbol, proceeds and gain/loss as an earlier
+    /// one instead of only warning about them. Identical lots sold together look the
+    /// same, so this is only safe when the files are known to overlap.
+    pub dedupe: bool,
+    /// Fail on the first row of an input file that cannot be parsed, rather than
+    /// skipping it with a warning (see [`SkippedRow`]). FIFO mode is always strict.
+    pub strict: bool,
+    /// Date the sales by their "Trade Date" column when the file has one, rather than
+    /// by their "Date Sold" (settlement) column, as CGT is due on the contract date.
+    pub use_trade_date: bool,
+    /// Layout of the input files (ignored in FIFO mode, which only reads ETrade exports).
+    pub broker_format: Arc<dyn BrokerFormat>,
+    /// Symbols whose transactions are left out of the reports (e.g. development land).
+    pub exclude_symbols: Vec<String>,
+    /// Accounts whose transactions are left out of the reports (e.g. a pension wrapper).
+    pub exclude_accounts: Vec<String>,
+    /// First and last day of Irish residence in the year of a change of residence (or
+    /// of death), outside which the disposals are left out of the reports.
+    pub resident_from: Option<Date>,
+    pub resident_until: Option<Date>,
+    /// Symbols whose disposals are disposals of development land (e.g. land-rich shares).
+    pub development_land_symbols: Vec<String>,
+    /// Format of the dates of the input files.
+    pub date_format: DateFormat,
+    /// Which exchange rate converts each transaction.
+    pub rate_strategy: RateStrategy,
+    /// Average rates of the given years, used instead of the ECB's with
+    /// [`RateStrategy::YearlyAverage`].
+    pub yearly_rates: BTreeMap<i32, Decimal>,
+}
+
+/// Which exchange rate converts each transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RateStrategy {
+    /// The rate of the day of the transaction (or of the nearest preceding business day).
+    #[default]
+    Daily,
+    /// A single rate for all the transactions of a fiscal year, the average of its daily
+    /// rates, which Revenue accepts for small amounts. The rates file is not used.
+    YearlyAverage,
+    /// The rate of the day of the transaction, or for a day without one, the rate
+    /// interpolated linearly by calendar days between those of the surrounding business
+    /// days (up to 7 days away). The nearest preceding rate is used when the following
+    /// one is not published yet.
+    Interpolated,
+}
+
+/// Format of the dates of the input files. Reading dates in the wrong format would
+/// put the sales in the wrong periods, so a format is only detected when all the
+/// dates of the file parse with it, and other formats give the same dates.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DateFormat {
+    /// The format of the broker's exports (see [`BrokerFormat::date_format`]).
+    #[default]
+    Broker,
+    /// `MM/DD/YYYY`, as in US exports.
+    MonthDayYear,
+    /// `DD/MM/YYYY`, as in European exports.
+    DayMonthYear,
+    /// `YYYY-MM-DD`, as in ISO 8601.
+    YearMonthDay,
+    /// Whichever of the broker's format and the above formats parses all the dates.
+    Auto,
+}
+
+impl Default for ReadParams {
+    fn default() -> Self {
+        Self {
+            from_currency: FROM_CURRENCY.to_string(),
+            to_currency: TO_CURRENCY.to_string(),
+            max_retries: MAX_RETRIES,
+            fetch_jobs: FETCH_JOBS,
+            timeout: REQUEST_TIMEOUT,
+            exr_base_url: None,
+            sheet: SHEET_NAME.to_string(),
+            round_to_cents: true,
+            round_mode: RoundMode::HalfUp,
+            exr_anomaly_threshold: Some(EXR_ANOMALY_THRESHOLD),
+            verbose: false,
+            rates_file: None,
+            refresh_rates: false,
+            audit_log: None,
+            check_cost_basis: true,
+            dedupe: false,
+            strict: false,
+            use_trade_date: true,
+            broker_format: Arc::new(ETrade),
+            exclude_symbols: Vec::new(),
+            exclude_accounts: Vec::new(),
+            resident_from: None,
+            resident_until: None,
+            development_land_symbols: Vec::new(),
+            date_format: DateFormat::Broker,
+            rate_strategy: RateStrategy::Daily,
+            yearly_rates: BTreeMap::new(),
+        }
+    }
+}
+
+/// Part of a fiscal year whose gains are taxed together, by the same deadline.
+#[derive(Debug, Clone, Copy)]
+pub struct PaymentPeriod {
+    pub start: Date,
+    pub end: Date,
+    pub due_date: Date,
+    pub label: &'static str,
+}
+
+/// Payment periods of a fiscal year: gains realised from January to November are
+/// due by December 15th, and gains realised in December by January 31st of the next
+/// year.
+pub fn payment_periods(fiscal_year: i32) -> Result<Vec<PaymentPeriod>> {
+    Ok(vec![
+        PaymentPeriod {
+            start: Date::from_calendar_date(fiscal_year, Month::January, 1)?,
+            end: Date::from_calendar_date(fiscal_year, Month::November, 30)?,
+            due_date: Date::from_calendar_date(fiscal_year, Month::December, 15)?,
+            label: "initial period",
+        },
+        PaymentPeriod {
+            start: Date::from_calendar_date(fiscal_year, Month::December, 1)?,
+            end: Date::from_calendar_date(fiscal_year, Month::December, 31)?,
+            due_date: Date::from_calendar_date(fiscal_year + 1, Month::January, 31)?,
+            label: "later period",
+        },
+    ])
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct TaxReport {
     pub fiscal_year: i32,
+    /// Currency the `usd_*` amounts of the report are actually denominated in.
+    pub currency: String,
+    /// Currency the `eur_*` amounts of the report are actually denominated in.
+    pub target_currency: String,
+    /// Where the exchange rates of the transactions come from: ECB series keys (see
+    /// [`ecb_series_key`]) or rates files. Empty when no conversion was needed.
+    pub rate_sources: Vec<String>,
+    /// Dates of the first and last disposal of the year, `None` without disposals.
+    pub disposal_dates: Option<(Date, Date)>,
+    /// Reports of the payment periods of the year (see [`payment_periods`]), in order.
+    pub payment_periods: Vec<PeriodTaxReport>,
     pub period_tax_report: PeriodTaxReport,
+    /// Tax of the year divided by its gains, 0 when there are no gains.
+    pub effective_rate: Decimal,
+    /// Part of the exemption already used by gains taxed outside the report.
+    pub eur_exemption_used: Eur,
+    /// Exemption left for this year's gains once that part is deducted.
+    pub eur_exemption: Eur,
+    /// Losses carried forward from previous years, deducted from this year's gains.
+    pub loss_carried_forward_in: Eur,
+    /// Losses left after offsetting this year's gains, to carry into next year.
+    pub loss_carried_forward_out: Eur,
+    /// Whether the proceeds of the year exceed the threshold above which a CGT return
+    /// is required, whether or not tax is due.
+    pub return_required: bool,
+    /// Tax of the year minus the tax of its payment periods. The tax of a period is due
+    /// on its own net gain, so a loss of a later period cannot undo the payment of an
+    /// earlier one: it reduces the tax of the year instead, and the difference (then
+    /// negative) is settled with the annual return.
+    pub eur_tax_adjustment: Eur,
 }
 
-#[derive(Debug, Default)]
+impl TaxReport {
+    /// One-line summary of the year, e.g. for a digest: `FY2023: net gain €2,064.96,
+    /// taxable €794.96, tax due €262.34 (return required)`. The amounts are rounded to
+    /// the cent with thousands separators, as in [`print_report`].
+    pub fn summary_line(&self) -> String {
+        let year = &self.period_tax_report;
+        let sym = currency_symbol(&self.target_currency);
+        format!(
+            "FY{}: net gain {}{}, taxable {}{}, tax due {}{} ({})",
+            self.fiscal_year,
+            sym,
+            year.eur_net_gain.formatted(),
+            sym,
+            year.eur_taxable_gain.formatted(),
+            sym,
+            year.eur_tax.formatted(),
+            if self.return_required {
+                "return required"
+            } else {
+                "no return required"
+            }
+        )
+    }
+}
+
+/// Key of the ECB series of the daily reference rates between two currencies, e.g.
+/// `D.USD.EUR.SP00.A`.
+pub fn ecb_series_key(from: &str, to: &str) -> String {
+    format!("D.{}.{}.SP00.A", from, to)
+}
+
+/// Key of the ECB series of the yearly averages of the daily reference rates between two
+/// currencies, e.g. `A.USD.EUR.SP00.A`.
+pub fn ecb_yearly_series_key(from: &str, to: &str) -> String {
+    format!("A.{}.{}.SP00.A", from, to)
+}
+
+/// Source of the rates interpolated between the observations of an ECB series.
+fn interpolated_source(series_key: &str) -> String {
+    format!("{} (interpolated)", series_key)
+}
+
+/// Source of the yearly average rates given in the parameters.
+const GIVEN_YEARLY_RATE_SOURCE: &str = "given yearly average";
+
+/// Date of the exchange rate a transaction is converted at with
+/// [`RateStrategy::YearlyAverage`]: the end of its year.
+fn yearly_rate_date(year: i32) -> Result<Date> {
+    Ok(Date::from_calendar_date(year, Month::December, 31)?)
+}
+
+/// Source currency, target currency and date of an exchange rate.
+#[cfg(feature = "io")]
+type RateKey = (String, String, Date);
+
+#[cfg(feature = "io")]
+#[derive(Debug)]
 struct ExchangeRateCache {
-    cache: HashMap<Date, f64>,
+    /// Rates of all the currency pairs, along with the date of their observation.
+    cache: HashMap<RateKey, (Decimal, Date)>,
+    from: String,
+    to: String,
+    max_retries: u32,
+    fetch_jobs: usize,
+    base_url: String,
+    /// Shared by all the requests, so that the connection to the ECB is reused.
+    client: reqwest::blocking::Client,
+    anomaly_threshold: Option<Decimal>,
+    verbose: bool,
+    /// Overwrite the cached rates differing from the fetched ones, instead of keeping them.
+    refresh: bool,
+    /// Counted atomically, as the requests of a prefetch are sent from several threads.
+    api_calls: AtomicUsize,
+    /// Rates file the overridden rates come from.
+    overrides: Option<(PathBuf, BTreeSet<RateKey>)>,
+    /// Locked, as the requests of a prefetch are sent from several threads.
+    audit_log: Option<Mutex<csv::Writer<File>>>,
+    strategy: RateStrategy,
+    /// Average rates of each currency and year, given or fetched, with
+    /// [`RateStrategy::YearlyAverage`].
+    yearly_rates: BTreeMap<(String, i32), Decimal>,
+    /// Years whose average rate of the account currency was given rather than fetched.
+    given_years: BTreeSet<i32>,
+    /// Rates interpolated between two observations, with [`RateStrategy::Interpolated`].
+    interpolated: BTreeSet<RateKey>,
 }
 
+#[cfg(feature = "io")]
 impl ExchangeRateCache {
-    fn new() -> Self {
-        Self::default()
+    fn from_params(params: &ReadParams) -> Result<Self> {
+        let mut cache = Self {
+            cache: HashMap::new(),
+            from: params.from_currency.clone(),
+            to: params.to_currency.clone(),
+            max_retries: params.max_retries,
+            fetch_jobs: params.fetch_jobs.max(1),
+            base_url: (params.exr_base_url.clone())
+                .or_else(|| std::env::var(EXR_BASE_URL_VAR).ok())
+                .unwrap_or_else(|| EXR_BASE_URL.to_string())
+                .trim_end_matches('/')
+                .to_string(),
+            client: reqwest::blocking::Client::builder()
+                .connect_timeout(params.timeout)
+                .timeout(params.timeout)
+                .build()?,
+            anomaly_threshold: params.exr_anomaly_threshold,
+            verbose: params.verbose,
+            refresh: params.refresh_rates,
+            audit_log: params
+                .audit_log
+                .as_ref()
+                .map(|file_path| open_audit_log(file_path).map(Mutex::new))
+                .transpose()?,
+            api_calls: AtomicUsize::new(0),
+            overrides: None,
+            strategy: params.rate_strategy,
+            yearly_rates: (params.yearly_rates.iter())
+                .map(|(year, exr)| ((params.from_currency.clone(), *year), *exr))
+                .collect(),
+            given_years: params.yearly_rates.keys().copied().collect(),
+            interpolated: BTreeSet::new(),
+        };
+        if let Some(rates_file) = &params.rates_file {
+            cache.load_overrides(rates_file)?;
+        }
+        Ok(cache)
+    }
+
+    fn key(&self, currency: &str, date: Date) -> RateKey {
+        (currency.to_string(), self.to.clone(), date)
+    }
+
+    /// Seeds the cache with user-provided rates, so that the ECB is never queried for
+    /// those dates. The file has `date,rate` columns for the rates of the cache's
+    /// currency pair, or `date,from,to,rate` columns for rates of any pair.
+    fn load_overrides<P: AsRef<Path>>(&mut self, file_path: P) -> Result<()> {
+        let mut rdr = csv::Reader::from_path(&file_path).with_context(|| {
+            format!(
+                "failed to open rates file {}",
+                file_path.as_ref().to_string_lossy()
+            )
+        })?;
+        let with_pairs = rdr.headers()?.len() >= 4;
+        let mut keys = BTreeSet::new();
+        for record in rdr.records() {
+            let record = record?;
+            let fields = if with_pairs {
+                (record.get(0), record.get(1), record.get(2), record.get(3))
+            } else {
+                (
+                    record.get(0),
+                    Some(self.from.as_str()),
+                    Some(self.to.as_str()),
+                    record.get(1),
+                )
+            };
+            let (Some(date), Some(from), Some(to), Some(exr)) = fields else {
+                return Err(Error::msg("rates file rows must have a date and a rate").into());
+            };
+            let date = Date::parse(date.trim(), EXR_API_DATE_FMT)
+                .with_context(|| format!("invalid date {:?} in rates file", date))?;
+            let exr = parse_exr(exr).context("invalid rate in rates file")?;
+            let key = (from.trim().to_string(), to.trim().to_string(), date);
+            self.cache.insert(key.clone(), (exr, date));
+            keys.insert(key);
+        }
+        self.overrides = Some((file_path.as_ref().to_path_buf(), keys));
+        Ok(())
+    }
+
+    /// Sends a GET request, retrying on network errors and 5xx responses only.
+    /// Returns `None` when the API has no data (404 response).
+    fn fetch(&self, url: &str) -> Result<Option<reqwest::blocking::Response>> {
+        let mut delay = RETRY_BASE_DELAY;
+        let mut attempt = 0;
+        loop {
+            if self.verbose {
+                eprintln!("GET {}", url);
+            }
+            self.api_calls.fetch_add(1, Ordering::Relaxed);
+            match self
+                .client
+                .get(url)
+                .send()
+                .and_then(|r| r.error_for_status())
+            {
+                Ok(r) => return Ok(Some(r)),
+                Err(e) if e.status() == Some(reqwest::StatusCode::NOT_FOUND) => return Ok(None),
+                Err(e)
+                    if attempt < self.max_retries
+                        && e.status().is_none_or(|s| s.is_server_error()) =>
+                {
+                    eprintln!(
+                        "Warning: exchange rate request failed ({}), retrying in {:?}",
+                        e, delay
+                    );
+                    thread::sleep(delay);
+                    delay *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
     }
 
-    fn get_exr(&mut self, date: Date) -> Result<f64> {
-        if let Some(exr) = self.cache.get(&date) {
+    /// Returns the exchange rate of the currency for the given date and the date of the
+    /// observation it comes from, which is the nearest preceding business day if the
+    /// ECB has no rate for that date (or the date itself for an interpolated rate).
+    fn get_exr(&mut self, currency: &str, date: Date) -> Result<(Decimal, Date)> {
+        if currency == self.to {
+            return Ok((Decimal::ONE, date));
+        }
+        if self.strategy == RateStrategy::YearlyAverage {
+            let exr = self.get_yearly_average(currency, date.year())?;
+            return Ok((exr, yearly_rate_date(date.year())?));
+        }
+        if let Some(exr) = self.cache.get(&self.key(currency, date)) {
+            if self.verbose {
+                eprintln!("Exchange rate for {}: {} (cache hit)", date, exr.0);
+            }
             return Ok(*exr);
         }
-        let date_str = date.format(EXR_API_DATE_FMT)?;
-        let r = reqwest::blocking::get(format!(
-            "https://data-api.ecb.europa.eu/service/data/EXR/D.{}.{}.SP00.A?detail=dataonly&startPeriod={}&endPeriod={}&format=csvdata",
-            FROM_CURRENCY, TO_CURRENCY, date_str, date_str))?;
-        let mut rdr = csv::Reader::from_reader(r);
-        let index = rdr
-            .headers()?
-            .iter()
-            .position(|h| h.trim() == "OBS_VALUE")
-            .context("failed to find EXR header")?;
-        let exr = &rdr
-            .records()
-            .next()
-            .context("missing entry from EXR CSV")??[index];
-        let exr = exr
-            .parse::<f64>()
-            .context("EXR field is not a valid float")?;
-        self.cache.insert(date, exr);
+        let exr = if self.strategy == RateStrategy::Interpolated {
+            let observations = self.fetch_range(currency, date, date)?;
+            let exr = interpolated_rate(&observations, date);
+            if exr.is_some_and(|(_, obs_date)| !observations.contains_key(&obs_date)) {
+                self.interpolated.insert(self.key(currency, date));
+            }
+            exr
+        } else {
+            let mut observations = self.fetch_observations(currency, date, date)?;
+            if observations.is_empty() {
+                let start = date - time::Duration::days(EXR_FALLBACK_DAYS);
+                observations = self.fetch_observations(currency, start, date)?;
+            }
+            observations
+                .into_iter()
+                .filter(|(obs_date, _)| *obs_date <= date)
+                .map(|(obs_date, exr)| (exr, obs_date))
+                .max_by_key(|(_, obs_date)| *obs_date)
+        };
+        let exr = exr.ok_or_else(|| {
+            if currency == self.from {
+                CgtError::ExchangeRateUnavailable(date)
+            } else {
+                CgtError::CurrencyRateUnavailable(currency.to_string(), date)
+            }
+        })?;
+        if self.verbose {
+            eprintln!("Exchange rate for {}: {} (network)", date, exr.0);
+        }
+        self.cache.insert(self.key(currency, date), exr);
         Ok(exr)
     }
-}
 
-fn get_column_indices(headers: Vec<String>) -> Result<(usize, usize, usize, usize)> {
-    let mut date_index: Option<usize> = None;
-    let mut gain_loss_index: Option<usize> = None;
-    let mut record_type_index: Option<usize> = None;
-    let mut total_proceeds_index: Option<usize> = None;
-    headers
-        .iter()
-        .enumerate()
-        .for_each(|(pos, h)| match h.trim() {
-            "Date Sold" => date_index = Some(pos),
-            "Adjusted Gain/Loss" => gain_loss_index = Some(pos),
-            "Record Type" => record_type_index = Some(pos),
-            "Total Proceeds" => total_proceeds_index = Some(pos),
-            _ => {}
-        });
-    Ok((
-        date_index.context("failed to find date header")?,
-        gain_loss_index.context("failed to find gain/loss header")?,
-        record_type_index.context("failed to find record type header")?,
-        total_proceeds_index.context("failed to find total proceeds header")?,
-    ))
-}
+    /// Describes where the rate of the currency for the given date comes from: the ECB
+    /// series, or the rates file overriding it. Empty when no conversion is needed.
+    fn exr_source(&self, currency: &str, date: Date) -> String {
+        match &self.overrides {
+            _ if currency == self.to => String::new(),
+            _ if self.strategy == RateStrategy::YearlyAverage => {
+                if currency == self.from && self.given_years.contains(&date.year()) {
+                    GIVEN_YEARLY_RATE_SOURCE.to_string()
+                } else {
+                    ecb_yearly_series_key(currency, &self.to)
+                }
+            }
+            Some((file_path, keys)) if keys.contains(&self.key(currency, date)) => {
+                file_path.display().to_string()
+            }
+            _ if self.interpolated.contains(&self.key(currency, date)) => {
+                interpolated_source(&ecb_series_key(currency, &self.to))
+            }
+            _ => ecb_series_key(currency, &self.to),
+        }
+    }
 
-pub fn get_transactions<P: AsRef<Path>>(file_path: P) -> Result<Vec<Transaction>> {
-    let mut spreadsheet: Xlsx<_> = open_workbook(file_path)?;
-    let Ok(range) = spreadsheet.worksheet_range("G&L_Expanded") else {
-        return Err(Error::msg("missing sheet"));
-    };
-    let headers = range.headers().context("failed to extract headers")?;
-    let (date_index, gain_loss_index, record_type_index, total_proceeds_index) =
-        get_column_indices(headers)?;
+    fn print_fetch_summary(&self, transaction_count: usize, dates: &BTreeSet<(String, Date)>) {
+        if self.verbose {
+            eprintln!(
+                "{} transactions, {} unique dates, {} API calls",
+                transaction_count,
+                dates.len(),
+                self.api_calls.load(Ordering::Relaxed)
+            );
+        }
+    }
 
-    let mut exr_cache = ExchangeRateCache::new();
-    let mut transactions = Vec::new();
+    /// Fetches the rates of all the given currencies and dates that are not cached yet
+    /// (or of all of them when refreshing), in one request per currency and year of
+    /// those dates. The requests are sent concurrently, [`Self::fetch_jobs`] at a time.
+    fn prefetch(&mut self, dates: &BTreeSet<(String, Date)>) -> Result<()> {
+        if self.strategy == RateStrategy::YearlyAverage {
+            return Ok(());
+        }
+        // First and last missing date of each currency and year
+        let mut ranges = BTreeMap::<(&str, i32), (Date, Date)>::new();
+        for (currency, date) in dates.iter().filter(|(currency, date)| {
+            *currency != self.to
+                && (self.refresh || !self.cache.contains_key(&self.key(currency, *date)))
+        }) {
+            ranges
+                .entry((currency, date.year()))
+                .and_modify(|(_, end)| *end = *date)
+                .or_insert((*date, *date));
+        }
+        let ranges: Vec<(&str, Date, Date)> = ranges
+            .into_iter()
+            .map(|((currency, _), (start, end))| (currency, start, end))
+            .collect();
+        let mut fetched = Vec::with_capacity(ranges.len());
+        for jobs in ranges.chunks(self.fetch_jobs) {
+            let cache = &*self;
+            let results: Vec<Result<BTreeMap<Date, Decimal>>> = thread::scope(|scope| {
+                let handles: Vec<_> = jobs
+                    .iter()
+                    .map(|&(currency, start, end)| {
+                        scope.spawn(move || cache.fetch_range(currency, start, end))
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|h| h.join().expect("exchange rate request panicked"))
+                    .collect()
+            });
+            for observations in results {
+                fetched.push(observations?);
+            }
+        }
+        for ((currency, start, end), observations) in ranges.into_iter().zip(fetched) {
+            self.cache_range(currency, start, end, &observations)?;
+        }
+        Ok(())
+    }
 
-    let mut year: i32 = 0;
+    /// Fetches all the observations needed for the rates of the currency from `start`
+    /// to `end` in a single request, including the following ones to interpolate from.
+    fn fetch_range(
+        &self,
+        currency: &str,
+        start: Date,
+        end: Date,
+    ) -> Result<BTreeMap<Date, Decimal>> {
+        let fallback_start = start - time::Duration::days(EXR_FALLBACK_DAYS);
+        let fallback_end = match self.strategy {
+            RateStrategy::Interpolated => end + time::Duration::days(EXR_FALLBACK_DAYS),
+            RateStrategy::Daily | RateStrategy::YearlyAverage => end,
+        };
+        Ok(self
+            .fetch_observations(currency, fallback_start, fallback_end)?
+            .into_iter()
+            .collect())
+    }
 
-    for r in range
-        .rows()
-        .skip(1)
-        .filter(|r| r[record_type_index] == Data::String("Sell".to_string()))
-    {
-        let sell_date = Date::parse(
-            r[date_index]
-                .as_string()
-                .context("wrong date field type")?
-                .as_str(),
-            &XLSX_DATE_FMT,
-        )?;
-        if year == 0 {
-            year = sell_date.year()
-        } else if year != sell_date.year() {
-            return Err(Error::msg("all cells should be from the same fiscal year"));
+    /// Caches the rates from `start` to `end` of the fetched observations, so that the
+    /// subsequent [`Self::get_exr`] calls for that range hit the cache. Rates already
+    /// in the cache are kept unless refreshing, with a warning if the ECB's differ
+    /// (e.g. a rate the ECB revised since it was written to the rates file).
+    fn cache_range(
+        &mut self,
+        currency: &str,
+        start: Date,
+        end: Date,
+        observations: &BTreeMap<Date, Decimal>,
+    ) -> Result<()> {
+        let mut date = start;
+        while date <= end {
+            if let Some(exr) = rate_with(self.strategy, observations, date) {
+                self.update(currency, date, exr);
+                if !observations.contains_key(&exr.1)
+                    && self.cache.get(&self.key(currency, date)) == Some(&exr)
+                {
+                    self.interpolated.insert(self.key(currency, date));
+                }
+            }
+            date = date.next_day().context("date out of range")?;
         }
+        Ok(())
+    }
 
-        let usd_proceeds = r[total_proceeds_index]
-            .as_f64()
-            .context("wrong total proceeds field type")?;
-
-        let gain_loss = r[gain_loss_index]
-            .as_f64()
-            .context("wrong gain/loss field type")?;
-        let (usd_gain, usd_loss) = if gain_loss >= 0. {
-            (gain_loss, 0.)
-        } else {
-            (0., -gain_loss)
+    /// Caches the fetched rate of a date, unless another rate is already cached for it.
+    fn update(&mut self, currency: &str, date: Date, exr: (Decimal, Date)) {
+        let key = self.key(currency, date);
+        let Some(cached) = self.cache.get(&key).copied() else {
+            self.cache.insert(key, exr);
+            return;
         };
-        let exr = exr_cache
-            .get_exr(sell_date)
-            .context("failed to retrieve exchange rate")?;
-        transactions.push(Transaction {
-            sell_date,
-            usd_gain,
-            usd_loss,
-            eur_gain: usd_gain / exr,
-            eur_loss: usd_loss / exr,
-            exr,
-            usd_proceeds,
-            eur_proceeds: usd_proceeds / exr,
-        });
+        if cached.0 == exr.0 {
+            return;
+        }
+        eprintln!(
+            "Warning: the ECB rate for {} ({}) differs from the cached rate ({}), using the {} one",
+            date,
+            exr.0,
+            cached.0,
+            if self.refresh { "ECB" } else { "cached" }
+        );
+        if self.refresh {
+            if let Some((_, keys)) = &mut self.overrides {
+                keys.remove(&key);
+            }
+            self.cache.insert(key, exr);
+        }
     }
-    Ok(transactions)
-}
 
-fn compute_period_report(
-    transactions: &[Transaction],
-    period: Option<(Date, Date)>,
-    exemption: f64,
-) -> PeriodTaxReport {
-    let (usd_gain, usd_loss, eur_gain, eur_loss, usd_proceeds, eur_proceeds) = transactions
-        .iter()
-        .filter(|t| {
-            if let Some((period_start, period_end)) = period {
-                t.sell_date >= period_start && t.sell_date <= period_end
+    /// Prints a warning for every fetched rate deviating from the median rate of its
+    /// currency pair and year by more than the anomaly threshold, which may reveal a
+    /// parsing bug or an ECB data glitch.
+    fn warn_anomalous_rates(&self) {
+        let Some(threshold) = self.anomaly_threshold else {
+            return;
+        };
+        // Keyed by currency pair and year
+        let mut by_year = BTreeMap::<_, Vec<(Date, Decimal)>>::new();
+        for ((from, to, _), (exr, obs_date)) in &self.cache {
+            by_year
+                .entry((from, to, obs_date.year()))
+                .or_default()
+                .push((*obs_date, *exr));
+        }
+        for rates in by_year.values_mut() {
+            rates.sort();
+            rates.dedup();
+            let mut values: Vec<Decimal> = rates.iter().map(|(_, exr)| *exr).collect();
+            values.sort();
+            let mid = values.len() / 2;
+            let median = if values.len().is_multiple_of(2) {
+                (values[mid - 1] + values[mid]) / Decimal::TWO
             } else {
-                true
+                values[mid]
+            };
+            for (obs_date, exr) in rates.iter() {
+                if ((*exr - median) / median).abs() > threshold {
+                    eprintln!(
+                        "Warning: exchange rate {} on {} deviates by more than {}% from the median rate of the year ({})",
+                        exr,
+                        obs_date,
+                        threshold * Decimal::ONE_HUNDRED,
+                        median
+                    );
+                }
             }
+        }
+    }
+
+    fn fetch_observations(
+        &self,
+        currency: &str,
+        start: Date,
+        end: Date,
+    ) -> Result<Vec<(Date, Decimal)>> {
+        self.fetch_series(
+            &ecb_series_key(currency, &self.to),
+            &start.format(EXR_API_DATE_FMT)?,
+            &end.format(EXR_API_DATE_FMT)?,
+        )?
+        .into_iter()
+        .map(|(obs_date, exr)| {
+            let obs_date =
+                Date::parse(&obs_date, EXR_API_DATE_FMT).context("EXR date is not a valid date")?;
+            Ok((obs_date, exr))
         })
-        .fold(
-            (0., 0., 0., 0., 0., 0.),
-            |(usd_gain, usd_loss, eur_gain, eur_loss, usd_proceeds, eur_proceeds), t| {
-                (
-                    usd_gain + t.usd_gain,
-                    usd_loss + t.usd_loss,
-                    eur_gain + t.eur_gain,
-                    eur_loss + t.eur_loss,
-                    usd_proceeds + t.usd_proceeds,
-                    eur_proceeds + t.eur_proceeds,
-                )
-            },
+        .collect()
+    }
+
+    /// Returns the average rate of the currency in the year, fetching it from the ECB's
+    /// series of the yearly averages unless it was given.
+    fn get_yearly_average(&mut self, currency: &str, year: i32) -> Result<Decimal> {
+        let key = (currency.to_string(), year);
+        if let Some(exr) = self.yearly_rates.get(&key) {
+            return Ok(*exr);
+        }
+        let series_key = ecb_yearly_series_key(currency, &self.to);
+        let period = year.to_string();
+        let exr = self
+            .fetch_series(&series_key, &period, &period)?
+            .into_iter()
+            .find(|(obs_period, _)| *obs_period == period)
+            .map(|(_, exr)| exr)
+            .ok_or(CgtError::YearlyRateUnavailable(year))?;
+        if self.verbose {
+            eprintln!("Yearly average exchange rate for {}: {}", year, exr);
+        }
+        self.yearly_rates.insert(key, exr);
+        Ok(exr)
+    }
+
+    /// Fetches the observations of an ECB series from `start` to `end`, which are
+    /// periods of the series (e.g. days or years), along with their period.
+    fn fetch_series(
+        &self,
+        series_key: &str,
+        start: &str,
+        end: &str,
+    ) -> Result<Vec<(String, Decimal)>> {
+        let url = format!(
+            "{}/{}?detail=dataonly&startPeriod={}&endPeriod={}&format=csvdata",
+            self.base_url, series_key, start, end
         );
-    let usd_net_gain = usd_gain - usd_loss;
-    let eur_net_gain = eur_gain - eur_loss;
-    let eur_chargeable_gain = f64::max(eur_net_gain - exemption, 0.);
-    let eur_tax = eur_chargeable_gain * TAX_RATE;
-    PeriodTaxReport {
-        usd_gain,
-        usd_loss,
-        usd_net_gain,
-        eur_gain,
-        eur_loss,
-        eur_net_gain,
-        usd_proceeds,
-        eur_proceeds,
-        eur_chargeable_gain,
-        eur_tax,
+        let Some(r) = self.fetch(&url)? else {
+            self.audit(&url, (start, end), &[])?;
+            return Ok(Vec::new());
+        };
+        let mut rdr = csv::Reader::from_reader(r);
+        let headers = rdr.headers()?;
+        let find = |name: &str| {
+            headers
+                .iter()
+                .position(|h| h.trim() == name)
+                .ok_or_else(|| CgtError::MissingHeader(name.to_string()))
+        };
+        let (date_index, value_index) = (find("TIME_PERIOD")?, find("OBS_VALUE")?);
+        let mut observations = Vec::new();
+        let mut audited = Vec::new();
+        let mut seen = BTreeSet::new();
+        for record in rdr.records() {
+            let record = record?;
+            let period = record[date_index].trim().to_string();
+            let exr = parse_exr(&record[value_index])?;
+            audited.push((period.clone(), record[value_index].to_string(), exr));
+            // Picking one of several observations for the same period would be a guess
+            if !seen.insert(period.clone()) {
+                return Err(
+                    Error::msg(format!("EXR CSV has several observations for {}", period)).into(),
+                );
+            }
+            observations.push((period, exr));
+        }
+        self.audit(&url, (start, end), &audited)?;
+        Ok(observations)
     }
-}
 
-fn compute_year_report(transactions: &[Transaction]) -> TaxReport {
-    let fiscal_year = transactions
-        .first()
-        .map(|t| t.sell_date.year())
-        .unwrap_or_default();
-    let period_tax_report = compute_period_report(transactions, None, EXEMPTION_EUR);
-    TaxReport {
-        fiscal_year,
-        period_tax_report,
+    /// Appends the observations answered to a request to the audit log, or a row
+    /// without observation when there are none.
+    fn audit(
+        &self,
+        url: &str,
+        (start, end): (&str, &str),
+        observations: &[(String, String, Decimal)],
+    ) -> Result<()> {
+        let Some(audit_log) = &self.audit_log else {
+            return Ok(());
+        };
+        let timestamp = OffsetDateTime::now_utc().format(&Rfc3339)?;
+        let mut writer = audit_log.lock().expect("audit log lock poisoned");
+        if observations.is_empty() {
+            writer.write_record([&timestamp, start, end, "", url, "", ""])?;
+        }
+        for (period, raw, exr) in observations {
+            let exr = exr.to_string();
+            writer.write_record([&timestamp, start, end, period, url, raw, &exr])?;
+        }
+        writer.flush()?;
+        Ok(())
     }
 }
 
-pub fn write_detail_as_csv<P: AsRef<Path>>(
-    transactions: &[Transaction],
-    file_path: P,
-) -> Result<()> {
-    let mut wtr = csv::Writer::from_path(&file_path)?;
-    wtr.write_record([
-        "Sell Date",
-        "USD Gain",
-        "USD Loss",
-        "EUR Gain",
-        "EUR Loss",
-        "EXR",
-        "USD Proceeds",
-        "EUR Proceeds",
-    ])?;
-    for t in transactions {
-        wtr.write_record(&[
-            t.sell_date.format(EXR_API_DATE_FMT)?,
-            t.usd_gain.to_string(),
-            t.usd_loss.to_string(),
-            t.eur_gain.to_string(),
-            t.eur_loss.to_string(),
-            t.exr.to_string(),
-            t.usd_proceeds.to_string(),
-            t.eur_proceeds.to_string(),
-        ])?;
+#[cfg(feature = "io")]
+const AUDIT_LOG_HEADERS: [&str; 7] = [
+    "Timestamp",
+    "Start Period",
+    "End Period",
+    "Observation Period",
+    "URL",
+    "OBS_VALUE",
+    "Rate",
+];
+
+#[cfg(feature = "io")]
+/// Opens the audit log for appending, writing its headers if it is new or empty.
+fn open_audit_log(file_path: &Path) -> Result<csv::Writer<File>> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(file_path)
+        .with_context(|| format!("failed to open audit log {}", file_path.display()))?;
+    let is_empty = file.metadata()?.len() == 0;
+    let mut writer = csv::Writer::from_writer(file);
+    if is_empty {
+        writer.write_record(AUDIT_LOG_HEADERS)?;
+        writer.flush()?;
     }
-    println!(
-        "The transaction detail was written as CSV to file {}",
-        file_path.as_ref().to_string_lossy()
-    );
-    Ok(())
+    Ok(writer)
 }
 
-pub fn compute_and_print_report(transactions: &[Transaction]) -> Result<()> {
-    let yr_report = compute_year_report(transactions);
-    let yr = yr_report.fiscal_year;
+#[cfg(feature = "io")]
+/// Parses an exchange rate, accepting a comma as decimal separator (e.g. `1,0845`). The
+/// amounts are divided by the rate, which must therefore be positive.
+fn parse_exr(raw: &str) -> Result<Decimal> {
+    let value = raw.trim();
+    let value = if value.matches(',').count() == 1 && !value.contains('.') {
+        value.replace(',', ".")
+    } else {
+        value.to_string()
+    };
+    value
+        .parse::<Decimal>()
+        .ok()
+        .filter(|exr| *exr > Decimal::ZERO)
+        .ok_or_else(|| CgtError::RateParse(raw.to_string()))
+}
 
-    // Jan 1st to Nov 30th
-    let period = (
-        Date::from_calendar_date(yr, Month::January, 1)?,
-        Date::from_calendar_date(yr, Month::November, 30)?,
-    );
-    print_period_header(period)?;
-    let period_report = compute_period_report(transactions, Some(period), EXEMPTION_EUR);
-    print_period_report(&period_report);
-
-    // Portion of the exemption that has been used in the first period (to deduct
-    // from the exemption in the second period). This can become negative if
-    // there was loss, as this loss can now be deducted from the next period too.
-    let used_exemption = f64::min(EXEMPTION_EUR, period_report.eur_net_gain);
-
-    // Dec 1st to Dec 31st
-    let period = (
-        Date::from_calendar_date(yr, Month::December, 1)?,
-        Date::from_calendar_date(yr, Month::December, 31)?,
-    );
-    print_period_header(period)?;
-    let period_report =
-        compute_period_report(transactions, Some(period), EXEMPTION_EUR - used_exemption);
-    print_period_report(&period_report);
+#[cfg(feature = "io")]
+struct ColumnIndices {
+    date: usize,
+    amounts: AmountColumns,
+    /// `None` when every row is a disposal.
+    record_type: Option<usize>,
+    symbol: Option<usize>,
+    cost_basis: Option<usize>,
+    account: Option<usize>,
+    trade_date: Option<usize>,
+    fees: Option<usize>,
+    currency: Option<usize>,
+}
 
-    // Full year
-    println!(
-        "\n=== TAX REPORT FOR ENTIRE FISCAL YEAR {} ===\n",
-        yr_report.fiscal_year
-    );
-    print_period_report(&yr_report.period_tax_report);
-    Ok(())
+#[cfg(feature = "io")]
+/// Columns the proceeds and the gain/loss of a sale are read from.
+enum AmountColumns {
+    /// The gain/loss as computed by the broker, and the total proceeds.
+    Netted {
+        gain_loss: usize,
+        total_proceeds: usize,
+    },
+    /// The quantity and price of the shares sold, and their total cost basis.
+    PerShare {
+        quantity: usize,
+        sale_price: usize,
+        cost_basis: usize,
+    },
 }
 
-fn print_period_header(period: (Date, Date)) -> Result<()> {
-    println!(
-        "\n=== TAX REPORT FOR PERIOD {} TO {} ===\n",
-        period.0.format(EXR_API_DATE_FMT)?,
-        period.1.format(EXR_API_DATE_FMT)?
-    );
-    Ok(())
+#[cfg(feature = "io")]
+impl AmountColumns {
+    /// Returns the proceeds and the gain/loss of a row, given how to parse the amount
+    /// of a column of the row.
+    fn read(&self, parse: impl Fn(usize, &str) -> Result<Decimal>) -> Result<(Decimal, Decimal)> {
+        match *self {
+            AmountColumns::Netted {
+                gain_loss,
+                total_proceeds,
+            } => Ok((
+                parse(total_proceeds, "total proceeds")?,
+                parse(gain_loss, "gain/loss")?,
+            )),
+            AmountColumns::PerShare {
+                quantity,
+                sale_price,
+                cost_basis,
+            } => {
+                let proceeds = parse(quantity, "quantity")? * parse(sale_price, "sale price")?;
+                Ok((proceeds, proceeds - parse(cost_basis, "cost basis")?))
+            }
+        }
+    }
 }
 
-fn print_period_report(report: &PeriodTaxReport) {
-    println!("Total proceeds (USD): ${:.2}", report.usd_proceeds);
-    println!("Total gain (USD): ${:.2}", report.usd_gain);
-    println!("Total loss (USD): ${:.2}", report.usd_loss);
-    println!("Net gain (USD): ${:.2}\n", report.usd_net_gain);
-    println!("Total proceeds: €{:.2}", report.eur_proceeds);
-    println!("Total gain: €{:.2}", report.eur_gain);
-    println!("Total loss: €{:.2}", report.eur_loss);
-    println!("Net gain (Gain-Loss): €{:.2}", report.eur_net_gain);
+#[cfg(feature = "io")]
+fn get_column_indices(headers: Vec<String>, format: &dyn BrokerFormat) -> Result<ColumnIndices> {
+    let find = |column: Column| {
+        headers
+            .iter()
+            .position(|h| format.header_names(column).contains(&h.trim()))
+    };
+    let require = |column: Column, name: &str| {
+        find(column).ok_or_else(|| CgtError::MissingHeader(name.to_string()))
+    };
+    let record_type = if format.header_names(Column::RecordType).is_empty() {
+        None
+    } else {
+        Some(require(Column::RecordType, "record type")?)
+    };
+    // Without the broker's gain/loss, it is computed from the price of the shares
+    let per_share = (
+        find(Column::Quantity),
+        find(Column::SalePrice),
+        find(Column::CostBasis),
+    );
+    let amounts = match (find(Column::GainLoss), per_share) {
+        (None, (Some(quantity), Some(sale_price), Some(cost_basis))) => AmountColumns::PerShare {
+            quantity,
+            sale_price,
+            cost_basis,
+        },
+        (gain_loss, _) => AmountColumns::Netted {
+            gain_loss: gain_loss.ok_or_else(|| CgtError::MissingHeader("gain/loss".to_string()))?,
+            total_proceeds: require(Column::TotalProceeds, "total proceeds")?,
+        },
+    };
+    Ok(ColumnIndices {
+        date: require(Column::DateSold, "date")?,
+        amounts,
+        record_type,
+        symbol: find(Column::Symbol),
+        cost_basis: find(Column::CostBasis),
+        account: find(Column::Account),
+        trade_date: find(Column::TradeDate),
+        fees: find(Column::Fees),
+        currency: find(Column::Currency),
+    })
+}
+
+#[cfg(feature = "io")]
+fn open_sheet<P: AsRef<Path>>(file_path: P, sheet: &str) -> Result<Range<Data>> {
+    let mut spreadsheet: Xlsx<_> = open_workbook(file_path)?;
+    let sheet_names = spreadsheet.sheet_names();
+    let sheet = match sheet_names.as_slice() {
+        [only_sheet] => only_sheet.clone(),
+        _ if sheet_names.iter().any(|s| s == sheet) => sheet.to_string(),
+        _ => {
+            return Err(CgtError::MissingSheet {
+                sheet: sheet.to_string(),
+                closest: closest_sheet(sheet, &sheet_names),
+                available: sheet_names,
+            })
+        }
+    };
+    Ok(spreadsheet.worksheet_range(&sheet)?)
+}
+
+#[cfg(feature = "io")]
+/// Lists the names of the sheets of a spreadsheet, in the order of the workbook.
+pub fn list_sheets<P: AsRef<Path>>(file_path: P) -> Result<Vec<String>> {
+    let spreadsheet: Xlsx<_> = open_workbook(file_path)?;
+    Ok(spreadsheet.sheet_names())
+}
+
+/// Largest number of edits for a sheet name to be suggested instead of a missing one.
+#[cfg(feature = "io")]
+const SHEET_SUGGESTION_MAX_EDITS: usize = 3;
+
+/// Name of the sheet closest to the missing one, ignoring the case, if it is close
+/// enough to be a likely typo.
+#[cfg(feature = "io")]
+fn closest_sheet(sheet: &str, sheet_names: &[String]) -> Option<String> {
+    let sheet = sheet.to_lowercase();
+    sheet_names
+        .iter()
+        .map(|name| (edit_distance(&sheet, &name.to_lowercase()), name))
+        .filter(|(edits, _)| *edits <= SHEET_SUGGESTION_MAX_EDITS)
+        .min_by_key(|(edits, _)| *edits)
+        .map(|(_, name)| name.clone())
+}
+
+/// Levenshtein distance between two strings, in characters.
+#[cfg(feature = "io")]
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(feature = "io")]
+fn parse_amount(cell: &Data, field: &str) -> Result<Decimal> {
+    if let Data::String(text) = cell {
+        return Ok(parse_money(text).with_context(|| format!("wrong {} field type", field))?);
+    }
+    let amount = cell
+        .as_f64()
+        .with_context(|| format!("wrong {} field type", field))?;
+    Ok(
+        Decimal::try_from(amount)
+            .with_context(|| format!("invalid {} amount {}", field, amount))?,
+    )
+}
+
+/// Parses an amount that some exports write as text, with a currency symbol and
+/// thousands separators, and between parentheses when negative: e.g. `$1,234.56`,
+/// `-$12.00` or `(123.45)`.
+pub fn parse_money(text: &str) -> Option<Decimal> {
+    let text = text.trim();
+    let (negative, text) = match text.strip_prefix('(').and_then(|t| t.strip_suffix(')')) {
+        Some(inner) => (true, inner),
+        None => (false, text),
+    };
+    let digits: String = text
+        .chars()
+        .filter(|c| !matches!(c, '$' | ',') && !c.is_whitespace())
+        .collect();
+    let amount: Decimal = digits.parse().ok()?;
+    Some(if negative { -amount } else { amount })
+}
+
+/// Parses a date cell, either a text in the given format or a cell Excel stores as a
+/// date (a number of days since its epoch, or an ISO 8601 text).
+#[cfg(feature = "io")]
+fn parse_date_with(cell: &Data, date_format: &[BorrowedFormatItem]) -> Result<Date> {
+    match cell {
+        Data::DateTime(datetime) => {
+            let datetime = datetime
+                .as_datetime()
+                .with_context(|| format!("invalid date cell {}", datetime))?;
+            Ok(OffsetDateTime::from_unix_timestamp(datetime.and_utc().timestamp())?.date())
+        }
+        Data::DateTimeIso(datetime) => Ok(Date::parse(
+            datetime.get(..10).unwrap_or(datetime),
+            ISO_DATE_FMT,
+        )?),
+        _ => Ok(Date::parse(
+            cell.as_string().context("wrong date field type")?.trim(),
+            date_format,
+        )?),
+    }
+}
+
+/// Picks the format to parse the given dates (all the dates of a file) with, as
+/// requested by the parameters.
+#[cfg(feature = "io")]
+fn resolve_date_format(
+    params: &ReadParams,
+    dates: &[String],
+) -> Result<&'static [BorrowedFormatItem<'static>]> {
+    let broker_format = params.broker_format.date_format();
+    let candidates = [
+        broker_format,
+        XLSX_DATE_FMT,
+        DAY_MONTH_YEAR_DATE_FMT,
+        ISO_DATE_FMT,
+    ];
+    let format = match params.date_format {
+        DateFormat::Broker => broker_format,
+        DateFormat::MonthDayYear => XLSX_DATE_FMT,
+        DateFormat::DayMonthYear => DAY_MONTH_YEAR_DATE_FMT,
+        DateFormat::YearMonthDay => ISO_DATE_FMT,
+        DateFormat::Auto => return detect_date_format(&candidates, dates),
+    };
+    // A date in another of the formats means the file is in that format, rather than
+    // that its row is malformed and can be skipped
+    let parses = |date: &str, format| Date::parse(date.trim(), format).is_ok();
+    if let Some(date) = dates
+        .iter()
+        .find(|date| !parses(date, format) && candidates.iter().any(|&other| parses(date, other)))
+    {
+        return Err(CgtError::WrongDateFormat(date.clone()));
+    }
+    Ok(format)
+}
+
+/// Returns the first of the candidate formats that parses all the dates, as long as
+/// every other format parsing them all gives the same dates (e.g. when the day of
+/// every date is its month too).
+#[cfg(feature = "io")]
+fn detect_date_format(
+    candidates: &[&'static [BorrowedFormatItem<'static>]],
+    dates: &[String],
+) -> Result<&'static [BorrowedFormatItem<'static>]> {
+    let parse_all = |format: &[BorrowedFormatItem]| {
+        dates
+            .iter()
+            .map(|date| Date::parse(date.trim(), format))
+            .collect::<std::result::Result<Vec<_>, _>>()
+            .ok()
+    };
+    let mut parsed = candidates
+        .iter()
+        .filter_map(|&format| parse_all(format).map(|parsed| (format, parsed)));
+    let (format, first) = parsed.next().ok_or(CgtError::UnknownDateFormat)?;
+    for (_, other) in parsed {
+        if let Some(i) = (0..dates.len()).find(|&i| first[i] != other[i]) {
+            return Err(CgtError::AmbiguousDateFormat(dates[i].clone()));
+        }
+    }
+    Ok(format)
+}
+
+#[cfg(feature = "io")]
+/// Reads the transactions of a spreadsheet covering a single fiscal year.
+pub fn get_transactions<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    convert_sales(read_xlsx_sales(file_path, params)?, params, true)
+}
+
+#[cfg(feature = "io")]
+/// Reads the transactions of a spreadsheet that may cover several fiscal years,
+/// grouped by the year of their sell date.
+pub fn get_transactions_by_year<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<BTreeMap<i32, Vec<Transaction>>> {
+    Ok(group_by_year(convert_sales(
+        read_xlsx_sales(file_path, params)?,
+        params,
+        false,
+    )?))
+}
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions`], for a CSV file with the same columns as the spreadsheet.
+pub fn get_transactions_from_csv<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    convert_sales(read_csv_sales(file_path, params)?, params, true)
+}
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions_by_year`], for a CSV file with the same columns as the spreadsheet.
+pub fn get_transactions_from_csv_by_year<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<BTreeMap<i32, Vec<Transaction>>> {
+    Ok(group_by_year(convert_sales(
+        read_csv_sales(file_path, params)?,
+        params,
+        false,
+    )?))
+}
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions_from_csv`], for CSV read from e.g. the standard input.
+pub fn get_transactions_from_reader<R: io::Read>(
+    reader: R,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    convert_sales(read_reader_sales(reader, params)?, params, true)
+}
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions_from_csv_by_year`], for CSV read from e.g. the standard input.
+pub fn get_transactions_from_reader_by_year<R: io::Read>(
+    reader: R,
+    params: &ReadParams,
+) -> Result<BTreeMap<i32, Vec<Transaction>>> {
+    Ok(group_by_year(convert_sales(
+        read_reader_sales(reader, params)?,
+        params,
+        false,
+    )?))
+}
+
+#[cfg(feature = "io")]
+/// Source file of the transactions read from a reader rather than from a file.
+pub const READER_SOURCE: &str = "stdin";
+
+#[cfg(feature = "io")]
+fn read_reader_sales<R: io::Read>(reader: R, params: &ReadParams) -> Result<Vec<Sale>> {
+    let (sales, skipped) = read_csv_records(
+        csv::Reader::from_reader(reader),
+        READER_SOURCE.to_string(),
+        params,
+    )?;
+    warn_skipped_rows(&sales, &skipped);
+    Ok(sales)
+}
+
+#[cfg(feature = "io")]
+/// Reads and merges the transactions of several spreadsheets or CSV files (e.g. one
+/// per brokerage account), grouped by the year of their sell date. CSV files are
+/// recognised by their extension, and the exchange rates are fetched once for all files.
+pub fn get_transactions_from_files_by_year<P: AsRef<Path>>(
+    file_paths: &[P],
+    params: &ReadParams,
+) -> Result<BTreeMap<i32, Vec<Transaction>>> {
+    let mut sales = Vec::new();
+    for file_path in file_paths {
+        sales.extend(read_sales(file_path, params)?);
+    }
+    sales.sort_by_key(|s| s.sell_date);
+    Ok(group_by_year(convert_sales(sales, params, false)?))
+}
+
+#[cfg(feature = "io")]
+/// Parses all the sales of a spreadsheet or CSV file without converting them, so
+/// that a malformed file is reported without querying the ECB. Returns the number
+/// of sales read.
+pub fn check_file<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<usize> {
+    let sales = read_sales(file_path, params)?;
+    if params.check_cost_basis {
+        warn_inconsistent_sales(&sales);
+    }
+    Ok(sales.len())
+}
+
+#[cfg(feature = "io")]
+type TransactionsByYear = BTreeMap<i32, Vec<Transaction>>;
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions_from_files_by_year`] for a single file, returning the rows
+/// skipped because they could not be parsed rather than printing them. Unless
+/// [`ReadParams::strict`] is set, a file is only rejected when none of its rows is valid.
+pub fn get_transactions_with_skipped_rows<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<(TransactionsByYear, Vec<SkippedRow>)> {
+    let (sales, skipped) = read_rows(file_path, params)?;
+    Ok((group_by_year(convert_sales(sales, params, false)?), skipped))
+}
+
+#[cfg(feature = "io")]
+fn read_sales<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<Vec<Sale>> {
+    let (sales, skipped) = read_rows(file_path, params)?;
+    warn_skipped_rows(&sales, &skipped);
+    Ok(sales)
+}
+
+#[cfg(feature = "io")]
+fn read_rows<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<ParsedRows> {
+    if is_csv(&file_path) {
+        read_csv_rows(file_path, params)
+    } else {
+        read_xlsx_rows(file_path, params)
+    }
+}
+
+#[cfg(feature = "io")]
+fn is_csv<P: AsRef<Path>>(file_path: P) -> bool {
+    file_path
+        .as_ref()
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+pub fn group_by_year(transactions: Vec<Transaction>) -> BTreeMap<i32, Vec<Transaction>> {
+    let mut by_year: BTreeMap<i32, Vec<Transaction>> = BTreeMap::new();
+    for t in transactions {
+        by_year.entry(t.sell_date.year()).or_default().push(t);
+    }
+    by_year
+}
+
+/// Distinct sell dates of the transactions, in order, e.g. to tell for how many dates
+/// rates are needed.
+pub fn unique_sell_dates(transactions: &[Transaction]) -> BTreeSet<Date> {
+    transactions.iter().map(|t| t.sell_date).collect()
+}
+
+/// A record realising a gain or a loss (see [`BrokerFormat::is_sale_record`]), as read
+/// from the input file. Amounts are in the currency of the account.
+#[derive(Debug, Clone)]
+pub struct Sale {
+    pub sell_date: Date,
+    pub gain_loss: Decimal,
+    pub usd_proceeds: Decimal,
+    pub symbol: String,
+    pub source_file: String,
+    pub cost_basis: Option<Decimal>,
+    pub account: String,
+    /// Commissions and fees, deducted from `gain_loss` when converting the sale.
+    pub fees: Decimal,
+    /// Currency of the amounts, `None` when they are in the currency of the account.
+    pub currency: Option<String>,
+}
+
+/// Row of an input file left out of the report because it could not be parsed.
+#[derive(Debug, Clone)]
+pub struct SkippedRow {
+    pub source_file: String,
+    /// Number of the row in the file, the header being row 1.
+    pub row: usize,
+    pub error: String,
+}
+
+#[cfg(feature = "io")]
+/// Sales read from the rows of a file, and the rows that could not be parsed.
+type ParsedRows = (Vec<Sale>, Vec<SkippedRow>);
+
+#[cfg(feature = "io")]
+/// Keeps the sales of the rows that parsed, and records the others as skipped. Fails on
+/// the first invalid row in strict mode, and when no row is valid, as the file is then
+/// more likely in the wrong layout than damaged.
+fn sort_rows(
+    rows: impl Iterator<Item = (usize, Result<Sale>)>,
+    source_file: &str,
+    strict: bool,
+) -> Result<ParsedRows> {
+    let mut sales = Vec::new();
+    let mut skipped = Vec::new();
+    let mut first_error = None;
+    for (row, sale) in rows {
+        match sale {
+            Ok(sale) => sales.push(sale),
+            Err(error) if strict => return Err(error),
+            Err(error) => {
+                skipped.push(SkippedRow {
+                    source_file: source_file.to_string(),
+                    row,
+                    error: error.to_string(),
+                });
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    match first_error {
+        Some(error) if sales.is_empty() => Err(error),
+        _ => Ok((sales, skipped)),
+    }
+}
+
+#[cfg(feature = "io")]
+fn warn_skipped_rows(sales: &[Sale], skipped: &[SkippedRow]) {
+    if skipped.is_empty() {
+        return;
+    }
+    for row in skipped {
+        eprintln!(
+            "Warning: skipped row {} of {}: {}",
+            row.row, row.source_file, row.error
+        );
+    }
+    eprintln!(
+        "Warning: {} of {} rows skipped",
+        skipped.len(),
+        sales.len() + skipped.len()
+    );
+}
+
+impl Sale {
+    fn currency<'a>(&'a self, params: &'a ReadParams) -> &'a str {
+        self.currency.as_deref().unwrap_or(&params.from_currency)
+    }
+}
+
+#[cfg(feature = "io")]
+fn read_xlsx_sales<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<Vec<Sale>> {
+    let (sales, skipped) = read_xlsx_rows(file_path, params)?;
+    warn_skipped_rows(&sales, &skipped);
+    Ok(sales)
+}
+
+#[cfg(feature = "io")]
+fn read_xlsx_rows<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<ParsedRows> {
+    let source_file = file_path.as_ref().display().to_string();
+    let range = open_sheet(file_path, &params.sheet)?;
+    let first_row = range.start().map_or(0, |(row, _)| row as usize);
+    let headers = range.headers().context("failed to extract headers")?;
+    let format = params.broker_format.as_ref();
+    let cols = get_column_indices(headers, format)?;
+    let trade_date = cols.trade_date.filter(|_| params.use_trade_date);
+    let date_cell = |r: &[Data]| -> usize {
+        trade_date
+            .filter(|&i| !r[i].is_empty())
+            .unwrap_or(cols.date)
+    };
+    // Numbered from 1 for the first row of the sheet
+    let sale_rows: Vec<(usize, &[Data])> = range
+        .rows()
+        .enumerate()
+        .skip(1)
+        .map(|(i, r)| (first_row + i + 1, r))
+        .filter(|(_, r)| {
+            cols.record_type.is_none_or(|i| {
+                r[i].get_string()
+                    .is_some_and(|record_type| format.is_sale_record(record_type))
+            })
+        })
+        .collect();
+    let dates: Vec<String> = sale_rows
+        .iter()
+        .filter_map(|(_, r)| r[date_cell(r)].get_string().map(str::to_string))
+        .collect();
+    let date_format = resolve_date_format(params, &dates)?;
+
+    let rows = sale_rows.into_iter().map(|(row, r)| {
+        let sale = read_xlsx_sale(r, date_cell(r), &cols, date_format, &source_file);
+        (row, sale)
+    });
+    sort_rows(rows, &source_file, params.strict)
+}
+
+#[cfg(feature = "io")]
+fn read_xlsx_sale(
+    r: &[Data],
+    date: usize,
+    cols: &ColumnIndices,
+    date_format: &[BorrowedFormatItem<'static>],
+    source_file: &str,
+) -> Result<Sale> {
+    let (usd_proceeds, gain_loss) = cols.amounts.read(|i, field| parse_amount(&r[i], field))?;
+    Ok(Sale {
+        sell_date: parse_date_with(&r[date], date_format)?,
+        usd_proceeds,
+        gain_loss,
+        symbol: cols
+            .symbol
+            .and_then(|i| r[i].as_string())
+            .unwrap_or_default(),
+        source_file: source_file.to_string(),
+        cost_basis: cols
+            .cost_basis
+            .map(|i| &r[i])
+            .filter(|cell| !cell.is_empty())
+            .map(|cell| parse_amount(cell, "cost basis"))
+            .transpose()?,
+        account: cols
+            .account
+            .map(|i| r[i].to_string().trim().to_string())
+            .unwrap_or_default(),
+        fees: cols
+            .fees
+            .map(|i| &r[i])
+            .filter(|cell| !cell.is_empty())
+            .map(|cell| parse_amount(cell, "fees"))
+            .transpose()?
+            .unwrap_or_default()
+            .abs(),
+        currency: cols
+            .currency
+            .and_then(|i| r[i].get_string())
+            .map(|currency| currency.trim().to_uppercase())
+            .filter(|currency| !currency.is_empty()),
+    })
+}
+
+#[cfg(feature = "io")]
+fn read_csv_sales<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<Vec<Sale>> {
+    let (sales, skipped) = read_csv_rows(file_path, params)?;
+    warn_skipped_rows(&sales, &skipped);
+    Ok(sales)
+}
+
+#[cfg(feature = "io")]
+fn read_csv_rows<P: AsRef<Path>>(file_path: P, params: &ReadParams) -> Result<ParsedRows> {
+    let source_file = file_path.as_ref().display().to_string();
+    read_csv_records(csv::Reader::from_path(file_path)?, source_file, params)
+}
+
+#[cfg(feature = "io")]
+fn read_csv_records<R: io::Read>(
+    mut rdr: csv::Reader<R>,
+    source_file: String,
+    params: &ReadParams,
+) -> Result<ParsedRows> {
+    let headers = rdr.headers()?.iter().map(str::to_string).collect();
+    let format = params.broker_format.as_ref();
+    let cols = get_column_indices(headers, format)?;
+    let mut sale_records = Vec::new();
+    for r in rdr.records() {
+        let r = r?;
+        if cols
+            .record_type
+            .is_none_or(|i| format.is_sale_record(&r[i]))
+        {
+            sale_records.push(r);
+        }
+    }
+    let dates: Vec<String> = (sale_records.iter())
+        .map(|r| csv_sell_date(r, &cols, params.use_trade_date).to_string())
+        .collect();
+    let date_format = resolve_date_format(params, &dates)?;
+
+    let mut rows = Vec::with_capacity(sale_records.len());
+    for (r, sell_date) in sale_records.iter().zip(&dates) {
+        let row = r.position().map_or(0, |position| position.line() as usize);
+        rows.push((
+            row,
+            read_csv_sale(r, sell_date, &cols, date_format, &source_file),
+        ));
+    }
+    sort_rows(rows.into_iter(), &source_file, params.strict)
+}
+
+#[cfg(feature = "io")]
+fn read_csv_sale(
+    r: &csv::StringRecord,
+    sell_date: &str,
+    cols: &ColumnIndices,
+    date_format: &[BorrowedFormatItem<'static>],
+    source_file: &str,
+) -> Result<Sale> {
+    let (usd_proceeds, gain_loss) = cols.amounts.read(|i, field| {
+        Ok(parse_money(&r[i]).with_context(|| format!("wrong {} field type", field))?)
+    })?;
+    Ok(Sale {
+        sell_date: Date::parse(sell_date, date_format)?,
+        usd_proceeds,
+        gain_loss,
+        symbol: cols
+            .symbol
+            .map(|i| r[i].trim().to_string())
+            .unwrap_or_default(),
+        source_file: source_file.to_string(),
+        cost_basis: cols
+            .cost_basis
+            .map(|i| r[i].trim())
+            .filter(|cost_basis| !cost_basis.is_empty())
+            .map(|cost_basis| parse_money(cost_basis).context("wrong cost basis field type"))
+            .transpose()?,
+        account: cols
+            .account
+            .map(|i| r[i].trim().to_string())
+            .unwrap_or_default(),
+        fees: cols
+            .fees
+            .map(|i| r[i].trim())
+            .filter(|fees| !fees.is_empty())
+            .map(|fees| parse_money(fees).context("wrong fees field type"))
+            .transpose()?
+            .unwrap_or_default()
+            .abs(),
+        currency: csv_currency(r, cols),
+    })
+}
+
+#[cfg(feature = "io")]
+/// Date of the sale of a CSV record, its trade date when it has one and it is used.
+fn csv_sell_date<'r>(
+    r: &'r csv::StringRecord,
+    cols: &ColumnIndices,
+    use_trade_date: bool,
+) -> &'r str {
+    cols.trade_date
+        .filter(|_| use_trade_date)
+        .map(|i| r[i].trim())
+        .filter(|date| !date.is_empty())
+        .unwrap_or(r[cols.date].trim())
+}
+
+#[cfg(feature = "io")]
+fn csv_currency(r: &csv::StringRecord, cols: &ColumnIndices) -> Option<String> {
+    cols.currency
+        .map(|i| r[i].trim().to_uppercase())
+        .filter(|currency| !currency.is_empty())
+}
+
+#[cfg(feature = "io")]
+/// Reads the transactions of a CSV file one at a time, for files too large to keep all
+/// their transactions in memory (see [`build_report_from_iter`]). The file is read
+/// twice: first for the dates whose exchange rates are fetched, then for the sales,
+/// which are converted as the iterator is consumed. The rows that cannot be parsed are
+/// yielded as errors, and duplicate sales are not detected.
+pub fn stream_transactions_from_csv<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+) -> Result<impl Iterator<Item = Result<Transaction>>> {
+    check_target_currency(params)?;
+    let format = params.broker_format.as_ref();
+    let mut rdr = csv::Reader::from_path(&file_path)?;
+    let headers = rdr.headers()?.iter().map(str::to_string).collect();
+    let cols = get_column_indices(headers, format)?;
+    // Only the distinct dates are kept, which are few even for a long history
+    let mut sale_dates = BTreeSet::new();
+    for r in rdr.records() {
+        let r = r?;
+        if cols
+            .record_type
+            .is_none_or(|i| format.is_sale_record(&r[i]))
+        {
+            let currency = csv_currency(&r, &cols).unwrap_or_else(|| params.from_currency.clone());
+            let sell_date = csv_sell_date(&r, &cols, params.use_trade_date).to_string();
+            sale_dates.insert((currency, sell_date));
+        }
+    }
+    let dates: Vec<String> = sale_dates.iter().map(|(_, date)| date.clone()).collect();
+    let date_format = resolve_date_format(params, &dates)?;
+    let rate_dates: BTreeSet<(String, Date)> = (sale_dates.into_iter())
+        .filter_map(|(currency, date)| Some((currency, Date::parse(&date, date_format).ok()?)))
+        .collect();
+
+    let mut exr_cache = ExchangeRateCache::from_params(params)?;
+    exr_cache
+        .prefetch(&rate_dates)
+        .context("failed to retrieve exchange rates")?;
+    exr_cache.warn_anomalous_rates();
+    Ok(TransactionStream {
+        records: csv::Reader::from_path(&file_path)?.into_records(),
+        source_file: file_path.as_ref().display().to_string(),
+        cols,
+        date_format,
+        exr_cache,
+        params: params.clone(),
+    })
+}
+
+#[cfg(feature = "io")]
+/// Sales of a CSV file converted one at a time (see [`stream_transactions_from_csv`]).
+struct TransactionStream {
+    records: csv::StringRecordsIntoIter<File>,
+    source_file: String,
+    cols: ColumnIndices,
+    date_format: &'static [BorrowedFormatItem<'static>],
+    exr_cache: ExchangeRateCache,
+    params: ReadParams,
+}
+
+#[cfg(feature = "io")]
+impl TransactionStream {
+    fn convert(&mut self, r: &csv::StringRecord) -> Result<Transaction> {
+        let params = &self.params;
+        let sell_date = csv_sell_date(r, &self.cols, params.use_trade_date);
+        let sale = read_csv_sale(
+            r,
+            sell_date,
+            &self.cols,
+            self.date_format,
+            &self.source_file,
+        )?;
+        if params.check_cost_basis {
+            warn_inconsistent_sales(std::slice::from_ref(&sale));
+        }
+        let currency = sale.currency(params).to_string();
+        let exr = self
+            .exr_cache
+            .get_exr(&currency, sale.sell_date)
+            .context("failed to retrieve exchange rate")?;
+        let exr_series = self.exr_cache.exr_source(&currency, sale.sell_date);
+        let mut transaction = [sale_to_transaction(sale, exr, exr_series, params)];
+        flag_transactions(&mut transaction, params);
+        let [transaction] = transaction;
+        Ok(transaction)
+    }
+}
+
+#[cfg(feature = "io")]
+impl Iterator for TransactionStream {
+    type Item = Result<Transaction>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let r = match self.records.next()? {
+                Ok(r) => r,
+                Err(error) => return Some(Err(error.into())),
+            };
+            let format = self.params.broker_format.as_ref();
+            if self
+                .cols
+                .record_type
+                .is_none_or(|i| format.is_sale_record(&r[i]))
+            {
+                return Some(self.convert(&r));
+            }
+        }
+    }
+}
+
+/// Converts already parsed sales with the given exchange rates, indexed by the date of
+/// their observation, instead of fetching them. This does not need any network or file
+/// access, e.g. to compute the tax in WebAssembly. As with the ECB rates, a sale dated
+/// without an observation takes the rate of the nearest preceding one, up to 7 days before.
+/// A rate that is not positive is rejected.
+pub fn convert_sales_with_rates(
+    sales: Vec<Sale>,
+    rates: &BTreeMap<Date, Decimal>,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    let sales = handle_duplicate_sales(sales, params);
+    check_sales(&sales, params, false)?;
+    let mut transactions = Vec::with_capacity(sales.len());
+    for sale in sales {
+        let year = sale.sell_date.year();
+        let currency = sale.currency(params);
+        let exr = if currency == params.to_currency {
+            (Decimal::ONE, sale.sell_date)
+        } else if currency != params.from_currency {
+            // The given rates are those of the account currency only
+            return Err(CgtError::CurrencyRateUnavailable(
+                currency.to_string(),
+                sale.sell_date,
+            ));
+        } else if params.rate_strategy == RateStrategy::YearlyAverage {
+            let exr = params
+                .yearly_rates
+                .get(&year)
+                .ok_or(CgtError::YearlyRateUnavailable(year))?;
+            (*exr, yearly_rate_date(year)?)
+        } else {
+            positive_rate(
+                rate_with(params.rate_strategy, rates, sale.sell_date)
+                    .ok_or(CgtError::ExchangeRateUnavailable(sale.sell_date))?,
+            )?
+        };
+        transactions.push(sale_to_transaction(sale, exr, String::new(), params));
+    }
+    flag_transactions(&mut transactions, params);
+    Ok(transactions)
+}
+
+/// Source of the exchange rates converting the sales, see
+/// [`convert_sales_with_provider`]. The ECB is the source of [`get_transactions`] and
+/// the other readers, and a `HashMap` of the rates by date can stand in for it, e.g.
+/// in tests.
+pub trait RateProvider {
+    /// Rate converting an amount of the currency into the target currency on the date,
+    /// along with the date of its observation.
+    fn get(&mut self, currency: &str, date: Date) -> Result<(Decimal, Date)>;
+
+    /// Where the rate of the date comes from, shown in the detail of the transactions.
+    fn source(&self, _currency: &str, _date: Date) -> String {
+        String::new()
+    }
+}
+
+/// Rates of the account currency observed on each date, without any fallback.
+impl RateProvider for HashMap<Date, Decimal> {
+    fn get(&mut self, _currency: &str, date: Date) -> Result<(Decimal, Date)> {
+        let exr = HashMap::get(self, &date).ok_or(CgtError::ExchangeRateUnavailable(date))?;
+        Ok((*exr, date))
+    }
+}
+
+#[cfg(feature = "io")]
+impl RateProvider for ExchangeRateCache {
+    fn get(&mut self, currency: &str, date: Date) -> Result<(Decimal, Date)> {
+        Ok(self
+            .get_exr(currency, date)
+            .context("failed to retrieve exchange rate")?)
+    }
+
+    fn source(&self, currency: &str, date: Date) -> String {
+        self.exr_source(currency, date)
+    }
+}
+
+/// Converts already parsed sales with the rates of the provider instead of those of
+/// the ECB. The sales in the target currency are not converted, and a rate that is not
+/// positive is rejected.
+pub fn convert_sales_with_provider(
+    sales: Vec<Sale>,
+    provider: &mut dyn RateProvider,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    let sales = handle_duplicate_sales(sales, params);
+    check_sales(&sales, params, false)?;
+    convert_checked_sales(sales, provider, params)
+}
+
+#[cfg(feature = "io")]
+/// Same as [`get_transactions`] for a spreadsheet or a CSV file, with the rates of the
+/// provider instead of those of the ECB.
+pub fn get_transactions_with_provider<P: AsRef<Path>>(
+    file_path: P,
+    params: &ReadParams,
+    provider: &mut dyn RateProvider,
+) -> Result<Vec<Transaction>> {
+    let sales = handle_duplicate_sales(read_sales(file_path, params)?, params);
+    check_sales(&sales, params, true)?;
+    convert_checked_sales(sales, provider, params)
+}
+
+fn convert_checked_sales(
+    sales: Vec<Sale>,
+    provider: &mut dyn RateProvider,
+    params: &ReadParams,
+) -> Result<Vec<Transaction>> {
+    let mut transactions = Vec::with_capacity(sales.len());
+    for sale in sales {
+        let currency = sale.currency(params).to_string();
+        let (exr, exr_series) = if currency == params.to_currency {
+            ((Decimal::ONE, sale.sell_date), String::new())
+        } else {
+            let exr = positive_rate(provider.get(&currency, sale.sell_date)?)?;
+            (exr, provider.source(&currency, sale.sell_date))
+        };
+        transactions.push(sale_to_transaction(sale, exr, exr_series, params));
+    }
+    flag_transactions(&mut transactions, params);
+    Ok(transactions)
+}
+
+/// Rejects a rate that is not positive, given by the caller rather than parsed.
+fn positive_rate((exr, date): (Decimal, Date)) -> Result<(Decimal, Date)> {
+    if exr > Decimal::ZERO {
+        Ok((exr, date))
+    } else {
+        Err(CgtError::InvalidExchangeRate(date, exr))
+    }
+}
+
+/// Rate of the latest observation at most [`EXR_FALLBACK_DAYS`] before the date, along
+/// with the date of that observation.
+fn latest_rate(rates: &BTreeMap<Date, Decimal>, date: Date) -> Option<(Decimal, Date)> {
+    rates
+        .range(date - time::Duration::days(EXR_FALLBACK_DAYS)..=date)
+        .next_back()
+        .map(|(obs_date, exr)| (*exr, *obs_date))
+}
+
+/// Rate of the date from the daily observations, interpolated or not depending on the
+/// strategy.
+fn rate_with(
+    strategy: RateStrategy,
+    rates: &BTreeMap<Date, Decimal>,
+    date: Date,
+) -> Option<(Decimal, Date)> {
+    match strategy {
+        RateStrategy::Interpolated => interpolated_rate(rates, date),
+        RateStrategy::Daily | RateStrategy::YearlyAverage => latest_rate(rates, date),
+    }
+}
+
+/// Rate of the date interpolated linearly by calendar days between the latest
+/// observation at most [`EXR_FALLBACK_DAYS`] before it and the first one at most as
+/// many days after, dated on the date itself. It is rounded to the precision of those
+/// observations. Without a following observation, this is the [`latest_rate`].
+fn interpolated_rate(rates: &BTreeMap<Date, Decimal>, date: Date) -> Option<(Decimal, Date)> {
+    let (before, before_date) = latest_rate(rates, date)?;
+    let after = rates
+        .range(date..=date + time::Duration::days(EXR_FALLBACK_DAYS))
+        .next();
+    match after {
+        Some((&after_date, &after)) if before_date < date => {
+            let elapsed = Decimal::from((date - before_date).whole_days());
+            let span = Decimal::from((after_date - before_date).whole_days());
+            let exr = before + (after - before) * elapsed / span;
+            let scale = before.scale().max(after.scale());
+            Some((
+                exr.round_dp_with_strategy(scale, RoundingStrategy::MidpointAwayFromZero),
+                date,
+            ))
+        }
+        _ => Some((before, before_date)),
+    }
+}
+
+#[cfg(feature = "io")]
+fn convert_sales(
+    sales: Vec<Sale>,
+    params: &ReadParams,
+    single_year: bool,
+) -> Result<Vec<Transaction>> {
+    let sales = handle_duplicate_sales(sales, params);
+    check_sales(&sales, params, single_year)?;
+    let mut exr_cache = ExchangeRateCache::from_params(params)?;
+    let dates: BTreeSet<(String, Date)> = sales
+        .iter()
+        .map(|s| (s.currency(params).to_string(), s.sell_date))
+        .collect();
+    exr_cache
+        .prefetch(&dates)
+        .context("failed to retrieve exchange rates")?;
+    let transactions = convert_checked_sales(sales, &mut exr_cache, params)?;
+    exr_cache.warn_anomalous_rates();
+    exr_cache.print_fetch_summary(transactions.len(), &dates);
+    Ok(transactions)
+}
+
+/// The amounts are converted to euro amounts, the currency of the tax parameters.
+fn check_target_currency(params: &ReadParams) -> Result<()> {
+    if params.to_currency == TO_CURRENCY {
+        Ok(())
+    } else {
+        Err(CgtError::UnsupportedTargetCurrency(
+            params.to_currency.clone(),
+        ))
+    }
+}
+
+fn check_sales(sales: &[Sale], params: &ReadParams, single_year: bool) -> Result<()> {
+    check_target_currency(params)?;
+    if let Some(first) = sales.first() {
+        if single_year
+            && sales
+                .iter()
+                .any(|s| s.sell_date.year() != first.sell_date.year())
+        {
+            return Err(CgtError::MixedFiscalYears);
+        }
+    }
+
+    if params.check_cost_basis {
+        warn_inconsistent_sales(sales);
+    }
+    warn_mixed_currencies(sales, params);
+    Ok(())
+}
+
+fn sale_to_transaction(
+    sale: Sale,
+    exr: (Decimal, Date),
+    exr_series: String,
+    params: &ReadParams,
+) -> Transaction {
+    let mut t = Transaction::from_usd(
+        sale.sell_date,
+        sale.gain_loss - sale.fees,
+        sale.usd_proceeds,
+        exr,
+    );
+    t.usd_fees = sale.fees;
+    t.eur_fees = Eur::convert(sale.fees, t.exr);
+    t.currency = sale.currency(params).to_string();
+    t.target_currency = params.to_currency.clone();
+    t.symbol = sale.symbol;
+    t.source_file = sale.source_file;
+    t.account = sale.account;
+    t.exr_series = exr_series;
+    if params.round_to_cents {
+        t.round_to_cents(params.round_mode);
+    }
+    t
+}
+
+/// Flags the excluded transactions and the disposals of development land.
+fn flag_transactions(transactions: &mut [Transaction], params: &ReadParams) {
+    for t in transactions {
+        let resident = params.resident_from.is_none_or(|from| t.sell_date >= from)
+            && params
+                .resident_until
+                .is_none_or(|until| t.sell_date <= until);
+        t.excluded = params.exclude_symbols.contains(&t.symbol)
+            || params.exclude_accounts.contains(&t.account)
+            || !resident;
+        t.development_land = params.development_land_symbols.contains(&t.symbol);
+    }
+}
+
+/// Prints a warning when the sales are in several currencies: each sale is converted
+/// at the rate of its own currency, but their totals in the account currency mix them.
+fn warn_mixed_currencies(sales: &[Sale], params: &ReadParams) {
+    let currencies: BTreeSet<&str> = sales.iter().map(|s| s.currency(params)).collect();
+    if currencies.len() > 1 {
+        eprintln!(
+            "Warning: the sales are in several currencies ({}), each is converted at the rate of its own currency but the totals in {} mix them",
+            currencies.into_iter().collect::<Vec<_>>().join(", "),
+            params.from_currency
+        );
+    }
+}
+
+/// Prints a warning for every sale with the same date, symbol, proceeds and gain/loss
+/// as an earlier one, e.g. a row of overlapping exports read twice, and removes it
+/// with [`ReadParams::dedupe`].
+fn handle_duplicate_sales(sales: Vec<Sale>, params: &ReadParams) -> Vec<Sale> {
+    let mut first_files = HashMap::new();
+    let mut kept = Vec::with_capacity(sales.len());
+    let mut duplicates = 0;
+    for sale in sales {
+        let key = (
+            sale.sell_date,
+            sale.symbol.clone(),
+            sale.usd_proceeds,
+            sale.gain_loss,
+        );
+        let Some(first_file) = first_files.get(&key) else {
+            first_files.insert(key, sale.source_file.clone());
+            kept.push(sale);
+            continue;
+        };
+        eprintln!(
+            "Warning: the sale of {} on {} in {} (proceeds {}, gain/loss {}) duplicates one in {}",
+            if sale.symbol.is_empty() {
+                "(no symbol)"
+            } else {
+                &sale.symbol
+            },
+            sale.sell_date,
+            sale.source_file,
+            sale.usd_proceeds,
+            sale.gain_loss,
+            first_file
+        );
+        duplicates += 1;
+        if !params.dedupe {
+            kept.push(sale);
+        }
+    }
+    if duplicates > 0 {
+        if params.dedupe {
+            eprintln!("Warning: {} duplicate sales removed", duplicates);
+        } else {
+            eprintln!(
+                "Warning: {} suspected duplicate sales are kept, as they may be identical lots sold together",
+                duplicates
+            );
+        }
+    }
+    kept
+}
+
+/// Prints a warning for every sale whose gain/loss does not reconcile with its
+/// proceeds and cost basis, which reveals an inconsistent export.
+fn warn_inconsistent_sales(sales: &[Sale]) {
+    for sale in sales {
+        let Some(cost_basis) = sale.cost_basis else {
+            continue;
+        };
+        let expected = sale.usd_proceeds - cost_basis;
+        if (expected - sale.gain_loss).abs() > COST_BASIS_TOLERANCE {
+            eprintln!(
+                "Warning: gain/loss of the sale on {} in {} ({}) differs from its proceeds minus its cost basis ({})",
+                sale.sell_date, sale.source_file, sale.gain_loss, expected
+            );
+        }
+    }
+}
+
+fn compute_period_report(
+    transactions: &[Transaction],
+    period: Option<(Date, Date)>,
+    reliefs: (Eur, Eur),
+    rates: (Decimal, Decimal),
+) -> PeriodTaxReport {
+    let mut totals = PeriodTotals::new(period);
+    for t in transactions {
+        totals.add(t);
+    }
+    totals.report(reliefs, rates)
+}
+
+/// Running totals of the disposals of a period, so that its report is computed without
+/// keeping its transactions.
+#[derive(Debug, Default)]
+struct PeriodTotals {
+    period: Option<(Date, Date)>,
+    transaction_count: usize,
+    usd_gain: Decimal,
+    usd_loss: Decimal,
+    eur_gain: Eur,
+    eur_loss: Eur,
+    usd_proceeds: Decimal,
+    eur_proceeds: Eur,
+    development_land_net_gain: Eur,
+}
+
+impl PeriodTotals {
+    fn new(period: Option<(Date, Date)>) -> Self {
+        Self {
+            period,
+            ..Self::default()
+        }
+    }
+
+    /// Adds the transaction if it is in the period and not excluded.
+    fn add(&mut self, t: &Transaction) {
+        if t.excluded
+            || (self.period).is_some_and(|(start, end)| t.sell_date < start || t.sell_date > end)
+        {
+            return;
+        }
+        self.transaction_count += 1;
+        self.usd_gain += t.usd_gain;
+        self.usd_loss += t.usd_loss;
+        self.eur_gain += t.eur_gain;
+        self.eur_loss += t.eur_loss;
+        // The negative proceeds of buying to cover are not proceeds of a disposal, and
+        // would reduce the total compared with the return threshold
+        self.usd_proceeds += t.usd_proceeds.max(Decimal::ZERO);
+        self.eur_proceeds += t.eur_proceeds.max(Eur::ZERO);
+        if t.development_land {
+            self.development_land_net_gain += t.eur_gain - t.eur_loss;
+        }
+    }
+
+    /// Net gains on other assets and on development land. Losses on development land
+    /// can be deducted from the other gains, but losses on other assets cannot be
+    /// deducted from gains on development land.
+    fn net_gains(&self) -> (Eur, Eur) {
+        let eur_net_gain = self.eur_gain - self.eur_loss;
+        if self.development_land_net_gain < Eur::ZERO {
+            (eur_net_gain, Eur::ZERO)
+        } else {
+            (
+                eur_net_gain - self.development_land_net_gain,
+                self.development_land_net_gain,
+            )
+        }
+    }
+
+    /// Exemption left once the other gains are reduced by the losses and then by the
+    /// exemption.
+    fn exemption_left(&self, (losses, exemption): (Eur, Eur)) -> Eur {
+        let (standard_net_gain, _) = self.net_gains();
+        Eur::max(
+            exemption - Eur::max(standard_net_gain - losses, Eur::ZERO),
+            Eur::ZERO,
+        )
+    }
+
+    /// Losses and exemption left for the next periods. A net loss of the period adds
+    /// to the losses.
+    fn reliefs_left(&self, reliefs: (Eur, Eur)) -> (Eur, Eur) {
+        let (standard_net_gain, development_land_net_gain) = self.net_gains();
+        let exemption_left = self.exemption_left(reliefs);
+        (
+            Eur::max(reliefs.0 - standard_net_gain, Eur::ZERO),
+            exemption_left - Eur::min(exemption_left, development_land_net_gain),
+        )
+    }
+
+    fn report(
+        &self,
+        (losses, exemption): (Eur, Eur),
+        (rate, development_land_rate): (Decimal, Decimal),
+    ) -> PeriodTaxReport {
+        let usd_net_gain = self.usd_gain - self.usd_loss;
+        let eur_net_gain = self.eur_gain - self.eur_loss;
+
+        // The losses and the exemption go to the other gains first, and only the
+        // exemption left goes to the gains on development land
+        let (standard_net_gain, development_land_net_gain) = self.net_gains();
+        let standard_taxable_gain = Eur::max(standard_net_gain - losses - exemption, Eur::ZERO);
+        let exemption_left = self.exemption_left((losses, exemption));
+        let eur_development_land_taxable_gain =
+            Eur::max(development_land_net_gain - exemption_left, Eur::ZERO);
+        let eur_development_land_tax = eur_development_land_taxable_gain * development_land_rate;
+        let eur_taxable_gain = standard_taxable_gain + eur_development_land_taxable_gain;
+        let eur_tax = standard_taxable_gain * rate + eur_development_land_tax;
+        PeriodTaxReport {
+            period: self.period,
+            due_date: None,
+            label: None,
+            transaction_count: self.transaction_count,
+            usd_gain: self.usd_gain,
+            usd_loss: self.usd_loss,
+            usd_net_gain,
+            eur_gain: self.eur_gain,
+            eur_loss: self.eur_loss,
+            eur_net_gain,
+            usd_proceeds: self.usd_proceeds,
+            eur_proceeds: self.eur_proceeds,
+            eur_allowance: losses + exemption,
+            eur_taxable_gain,
+            eur_tax,
+            eur_development_land_taxable_gain,
+            eur_development_land_tax,
+        }
+    }
+}
+
+/// Builds the report of the fiscal year of the given transactions, which all belong
+/// to the same year. Fails with [`CgtError::NoDisposals`] when there are none.
+pub fn build_report(transactions: &[Transaction], params: &TaxParams) -> Result<TaxReport> {
+    let fiscal_year = transactions
+        .first()
+        .map(|t| t.sell_date.year())
+        .ok_or(CgtError::NoDisposals)?;
+    build_report_for_periods(transactions, params, &payment_periods(fiscal_year)?)
+}
+
+/// Report of the disposals of an arbitrary period of a fiscal year, e.g. a quarter or
+/// the weeks around an event, with the whole exemption of the year (and the losses
+/// carried forward) as if it were the only period of the year. Fails when the period
+/// ends before it starts, spans several fiscal years, or is in a year without disposals.
+pub fn build_custom_period_report(
+    transactions: &[Transaction],
+    params: &TaxParams,
+    (start, end): (Date, Date),
+) -> Result<PeriodTaxReport> {
+    if start > end {
+        return Err(CgtError::InvalidPeriod(start, end));
+    }
+    if start.year() != end.year() {
+        return Err(CgtError::PeriodAcrossYears(start, end));
+    }
+    let years: BTreeSet<i32> = transactions.iter().map(|t| t.sell_date.year()).collect();
+    if !years.contains(&start.year()) {
+        return Err(CgtError::MissingYear {
+            year: start.year(),
+            available: years.into_iter().collect(),
+        });
+    }
+    Ok(compute_period_report(
+        transactions,
+        Some((start, end)),
+        params.reliefs(),
+        (params.rate, params.development_land_rate),
+    ))
+}
+
+/// Parses a `YYYY-MM-DD` date, the format of the dates of the reports.
+pub fn parse_iso_date(text: &str) -> Result<Date> {
+    Ok(Date::parse(text.trim(), EXR_API_DATE_FMT)?)
+}
+
+/// Same as [`build_report`] with other payment periods than the current Irish ones.
+pub fn build_report_for_periods(
+    transactions: &[Transaction],
+    params: &TaxParams,
+    periods: &[PaymentPeriod],
+) -> Result<TaxReport> {
+    let fiscal_year = transactions
+        .first()
+        .map(|t| t.sell_date.year())
+        .ok_or(CgtError::NoDisposals)?;
+    let mut totals = ReportTotals::new(fiscal_year, periods);
+    for t in transactions {
+        totals.add(t);
+    }
+    Ok(totals.report(params))
+}
+
+/// Same as [`build_report`] for transactions consumed one at a time, e.g. from
+/// [`stream_transactions_from_csv`], so that they are never all in memory. Fails on
+/// the first error of the transactions, and with [`CgtError::MixedFiscalYears`] when
+/// they are not all of the same year.
+pub fn build_report_from_iter<I>(transactions: I, params: &TaxParams) -> Result<TaxReport>
+where
+    I: IntoIterator<Item = Result<Transaction>>,
+{
+    let mut transactions = transactions.into_iter();
+    let first = transactions.next().ok_or(CgtError::NoDisposals)??;
+    let fiscal_year = first.sell_date.year();
+    let periods = payment_periods(fiscal_year)?;
+    let mut totals = ReportTotals::new(fiscal_year, &periods);
+    totals.add(&first);
+    for t in transactions {
+        let t = t?;
+        if t.sell_date.year() != fiscal_year {
+            return Err(CgtError::MixedFiscalYears);
+        }
+        totals.add(&t);
+    }
+    Ok(totals.report(params))
+}
+
+/// Running totals of the disposals of a fiscal year and of its payment periods.
+struct ReportTotals<'a> {
+    fiscal_year: i32,
+    periods: Vec<(&'a PaymentPeriod, PeriodTotals)>,
+    year: PeriodTotals,
+    /// Currencies of the first transaction.
+    currencies: Option<(String, String)>,
+    rate_sources: BTreeSet<String>,
+    disposal_dates: Option<(Date, Date)>,
+}
+
+impl<'a> ReportTotals<'a> {
+    fn new(fiscal_year: i32, periods: &'a [PaymentPeriod]) -> Self {
+        Self {
+            fiscal_year,
+            periods: (periods.iter())
+                .map(|period| (period, PeriodTotals::new(Some((period.start, period.end)))))
+                .collect(),
+            year: PeriodTotals::new(None),
+            currencies: None,
+            rate_sources: BTreeSet::new(),
+            disposal_dates: None,
+        }
+    }
+
+    fn add(&mut self, t: &Transaction) {
+        for (_, totals) in &mut self.periods {
+            totals.add(t);
+        }
+        self.year.add(t);
+        self.currencies
+            .get_or_insert_with(|| (t.currency.clone(), t.target_currency.clone()));
+        if !t.exr_series.is_empty() && !self.rate_sources.contains(&t.exr_series) {
+            self.rate_sources.insert(t.exr_series.clone());
+        }
+        self.disposal_dates = Some(match self.disposal_dates {
+            Some((first, last)) => (first.min(t.sell_date), last.max(t.sell_date)),
+            None => (t.sell_date, t.sell_date),
+        });
+    }
+
+    fn report(self, params: &TaxParams) -> TaxReport {
+        let rates = (params.rate, params.development_land_rate);
+        // Revenue applies the losses carried forward and the exemption to the first
+        // period first, and only what remains of them to the next ones. The loss of a
+        // period adds to the losses, as it can now be deducted from the next periods too.
+        let mut reliefs = params.reliefs();
+        let mut payment_periods = Vec::with_capacity(self.periods.len());
+        for (period, totals) in &self.periods {
+            let mut report = totals.report(reliefs, rates);
+            report.due_date = Some(period.due_date);
+            report.label = Some(period.label.to_string());
+            reliefs = totals.reliefs_left(reliefs);
+            payment_periods.push(report);
+        }
+
+        let period_tax_report = self.year.report(params.reliefs(), rates);
+        // The losses that could not be deducted from the gains on development land are
+        // left for the next year
+        let (loss_carried_forward_out, _) = self.year.reliefs_left(params.reliefs());
+        let return_required = period_tax_report.eur_proceeds > params.return_threshold_eur;
+        let eur_tax_adjustment =
+            period_tax_report.eur_tax - payment_periods.iter().map(|p| p.eur_tax).sum::<Eur>();
+        let effective_rate = if period_tax_report.eur_gain.is_zero() {
+            Decimal::ZERO
+        } else {
+            period_tax_report.eur_tax / period_tax_report.eur_gain
+        };
+        let (currency, target_currency) = self
+            .currencies
+            .unwrap_or_else(|| (FROM_CURRENCY.to_string(), TO_CURRENCY.to_string()));
+        TaxReport {
+            fiscal_year: self.fiscal_year,
+            currency,
+            target_currency,
+            rate_sources: self.rate_sources.into_iter().collect(),
+            disposal_dates: self.disposal_dates,
+            payment_periods,
+            period_tax_report,
+            effective_rate,
+            eur_exemption_used: params.exemption_used_eur,
+            eur_exemption: params.remaining_exemption(),
+            loss_carried_forward_in: params.loss_carried_forward_eur,
+            loss_carried_forward_out,
+            return_required,
+            eur_tax_adjustment,
+        }
+    }
+}
+
+/// Prints the currencies, the source of the exchange rates and the dates covered by
+/// the report, so that a printed report tells how it was computed.
+fn print_report_basis(report: &TaxReport) -> Result<()> {
+    println!("=== BASIS OF THE REPORT ===");
+    println!(
+        "Amounts converted from {} to {}",
+        report.currency, report.target_currency
+    );
+    let series_key = ecb_series_key(&report.currency, &report.target_currency);
+    let yearly_series_key = ecb_yearly_series_key(&report.currency, &report.target_currency);
+    if report.rate_sources.is_empty() {
+        println!("Exchange rates: none needed");
+    }
+    for source in &report.rate_sources {
+        if *source == series_key {
+            println!(
+                "Exchange rates: ECB daily reference rate (series {})",
+                source
+            );
+        } else if *source == interpolated_source(&series_key) {
+            println!(
+                "Exchange rates: ECB daily reference rate, interpolated on the days without one (series {})",
+                series_key
+            );
+        } else if *source == yearly_series_key {
+            println!(
+                "Exchange rates: ECB yearly average rate (series {})",
+                source
+            );
+        } else if source == GIVEN_YEARLY_RATE_SOURCE {
+            println!("Exchange rates: {} rate", source);
+        } else {
+            println!("Exchange rates: rates file {}", source);
+        }
+    }
+    if let Some((first, last)) = report.disposal_dates {
+        println!(
+            "Disposals from {} to {}",
+            first.format(EXR_API_DATE_FMT)?,
+            last.format(EXR_API_DATE_FMT)?
+        );
+    }
+    Ok(())
+}
+
+/// Totals of the reports of several fiscal years.
+#[derive(Debug, Default, Serialize)]
+pub struct LifetimeSummary {
+    /// First and last fiscal year summed, `None` without reports.
+    pub years: Option<(i32, i32)>,
+    pub eur_proceeds: Eur,
+    pub eur_net_gain: Eur,
+    pub eur_tax: Eur,
+}
+
+/// Sums the reports of several fiscal years. Only the totals of the years are summed:
+/// the exemption and the losses are applied within each year.
+pub fn summarise_years(reports: &[TaxReport]) -> LifetimeSummary {
+    let years = reports
+        .iter()
+        .map(|r| r.fiscal_year)
+        .min()
+        .zip(reports.iter().map(|r| r.fiscal_year).max());
+    let year_reports = || reports.iter().map(|r| &r.period_tax_report);
+    LifetimeSummary {
+        years,
+        eur_proceeds: year_reports().map(|r| r.eur_proceeds).sum(),
+        eur_net_gain: year_reports().map(|r| r.eur_net_gain).sum(),
+        eur_tax: year_reports().map(|r| r.eur_tax).sum(),
+    }
+}
+
+/// Compares the net gain of the reports, in the currency of the accounts, with the total
+/// printed on the broker's statement, e.g. `Net gain (USD): $2,200.00, matches the
+/// expected $2,200.00`. A difference reveals rows dropped or misread from the files.
+pub fn usd_gain_reconciliation(reports: &[TaxReport], expected: Decimal) -> String {
+    let currency = reports
+        .first()
+        .map_or(FROM_CURRENCY, |r| r.currency.as_str());
+    let sym = currency_symbol(currency);
+    let net_gain: Decimal = (reports.iter())
+        .map(|r| r.period_tax_report.usd_net_gain)
+        .sum();
+    let difference = (net_gain - expected).round_dp(2);
+    let comparison = if difference.is_zero() {
+        "matches".to_string()
+    } else {
+        format!(
+            "differs by {}{}{} from",
+            if difference > Decimal::ZERO { "+" } else { "-" },
+            sym,
+            format_money(difference.abs())
+        )
+    };
+    format!(
+        "Net gain ({}): {}{}, {} the expected {}{}",
+        currency,
+        sym,
+        format_money(net_gain),
+        comparison,
+        sym,
+        format_money(expected)
+    )
+}
+
+pub fn print_lifetime_summary(summary: &LifetimeSummary) {
+    let Some((first_year, last_year)) = summary.years else {
+        return;
+    };
+    println!(
+        "\n=== TOTAL OF FISCAL YEARS {} TO {} ===\n",
+        first_year, last_year
+    );
+    println!("Total proceeds: €{}", summary.eur_proceeds.formatted());
+    println!(
+        "Net gain: {}",
+        color::net(
+            summary.eur_net_gain.0,
+            format!("€{}", summary.eur_net_gain.formatted())
+        )
+    );
+    println!(
+        "Tax: {}",
+        color::tax(format!("€{}", summary.eur_tax.formatted()))
+    );
+    println!("The exemption and the losses apply to each year separately, not to this total.");
+}
+
+/// Builds a single report for several people (e.g. spouses or civil partners), one
+/// transaction set each, filing a joint return. The exemption is personal and cannot
+/// be transferred: each set is taxed against its own exemption, and the reports of the
+/// sets are then added up, so that the unused exemption of one person does not shelter
+/// the gains of another. The losses are shared: those one person cannot use reduce the
+/// gains of the others, in the order of the sets. The losses carried forward and the
+/// exemption already used given in the parameters are those of the person of the
+/// first set.
+pub fn build_pooled_report(
+    transaction_sets: &[Vec<Transaction>],
+    params: &TaxParams,
+) -> Result<TaxReport> {
+    let own_params = TaxParams {
+        loss_carried_forward_eur: Eur::ZERO,
+        exemption_used_eur: Eur::ZERO,
+        ..*params
+    };
+    let person_params = |i| if i == 0 { params } else { &own_params };
+    let mut reports = Vec::with_capacity(transaction_sets.len());
+    for (i, transactions) in transaction_sets.iter().enumerate() {
+        // A person without disposals this year leaves their exemption unused
+        if !transactions.is_empty() {
+            reports.push((i, build_report(transactions, person_params(i))?));
+        }
+    }
+
+    // The losses left by each person go to the taxable gains of the others
+    let mut spare_losses: Eur = reports
+        .iter()
+        .map(|(_, r)| r.loss_carried_forward_out)
+        .sum();
+    for (i, report) in &mut reports {
+        if spare_losses.is_zero() || report.period_tax_report.eur_taxable_gain.is_zero() {
+            continue;
+        }
+        let own = person_params(*i);
+        let shared = TaxParams {
+            loss_carried_forward_eur: own.loss_carried_forward_eur + spare_losses,
+            ..*own
+        };
+        *report = build_report(&transaction_sets[*i], &shared)?;
+        spare_losses = report.loss_carried_forward_out;
+        report.loss_carried_forward_in = own.loss_carried_forward_eur;
+    }
+
+    let mut pooled = reports
+        .into_iter()
+        .map(|(_, report)| report)
+        .reduce(|pooled, report| add_reports(pooled, &report))
+        .ok_or(CgtError::NoDisposals)?;
+    pooled.loss_carried_forward_out = spare_losses;
+    let year = &pooled.period_tax_report;
+    pooled.return_required = year.eur_proceeds > params.return_threshold_eur;
+    pooled.effective_rate = if year.eur_gain.is_zero() {
+        Decimal::ZERO
+    } else {
+        year.eur_tax / year.eur_gain
+    };
+    Ok(pooled)
+}
+
+/// Adds the figures of the report of another person of the same fiscal year.
+fn add_reports(mut total: TaxReport, other: &TaxReport) -> TaxReport {
+    for (period, other_period) in total.payment_periods.iter_mut().zip(&other.payment_periods) {
+        add_period_reports(period, other_period);
+    }
+    add_period_reports(&mut total.period_tax_report, &other.period_tax_report);
+    for source in &other.rate_sources {
+        if !total.rate_sources.contains(source) {
+            total.rate_sources.push(source.clone());
+        }
+    }
+    total.rate_sources.sort();
+    total.disposal_dates = match (total.disposal_dates, other.disposal_dates) {
+        (Some((first, last)), Some((other_first, other_last))) => {
+            Some((first.min(other_first), last.max(other_last)))
+        }
+        (dates, other_dates) => dates.or(other_dates),
+    };
+    total.eur_exemption_used += other.eur_exemption_used;
+    total.eur_exemption += other.eur_exemption;
+    total.loss_carried_forward_in += other.loss_carried_forward_in;
+    total.loss_carried_forward_out += other.loss_carried_forward_out;
+    total.eur_tax_adjustment += other.eur_tax_adjustment;
+    total
+}
+
+fn add_period_reports(total: &mut PeriodTaxReport, other: &PeriodTaxReport) {
+    total.transaction_count += other.transaction_count;
+    total.usd_gain += other.usd_gain;
+    total.usd_loss += other.usd_loss;
+    total.usd_net_gain += other.usd_net_gain;
+    total.eur_gain += other.eur_gain;
+    total.eur_loss += other.eur_loss;
+    total.eur_net_gain += other.eur_net_gain;
+    total.usd_proceeds += other.usd_proceeds;
+    total.eur_proceeds += other.eur_proceeds;
+    total.eur_allowance += other.eur_allowance;
+    total.eur_taxable_gain += other.eur_taxable_gain;
+    total.eur_tax += other.eur_tax;
+    total.eur_development_land_taxable_gain += other.eur_development_land_taxable_gain;
+    total.eur_development_land_tax += other.eur_development_land_tax;
+}
+
+#[cfg(feature = "io")]
+const DETAIL_HEADERS: [&str; 18] = [
+    "Sell Date",
+    "Symbol",
+    "USD Gain",
+    "USD Loss",
+    "EUR Gain",
+    "EUR Loss",
+    "EXR",
+    "EXR Date",
+    "EXR Series",
+    "USD Proceeds",
+    "EUR Proceeds",
+    "USD Fees",
+    "EUR Fees",
+    "Four-Week Rule",
+    "Source File",
+    "Account",
+    "Excluded",
+    "Development Land",
+];
+
+/// A field of the transaction detail, typed so that spreadsheets get numeric cells.
+#[cfg(feature = "io")]
+enum DetailField {
+    Text(String),
+    Amount(Decimal),
+}
+
+#[cfg(feature = "io")]
+impl std::fmt::Display for DetailField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DetailField::Text(text) => text.fmt(f),
+            DetailField::Amount(amount) => amount.fmt(f),
+        }
+    }
+}
+
+/// Fields of a transaction, in the order of [`DETAIL_HEADERS`].
+#[cfg(feature = "io")]
+fn detail_record(t: &Transaction) -> Result<[DetailField; 18]> {
+    use DetailField::{Amount, Text};
+    Ok([
+        Text(t.sell_date.format(EXR_API_DATE_FMT)?),
+        Text(t.symbol.clone()),
+        Amount(t.usd_gain),
+        Amount(t.usd_loss),
+        Amount(t.eur_gain.0),
+        Amount(t.eur_loss.0),
+        Amount(t.exr),
+        Text(t.exr_date.format(EXR_API_DATE_FMT)?),
+        Text(t.exr_series.clone()),
+        Amount(t.usd_proceeds),
+        Amount(t.eur_proceeds.0),
+        Amount(t.usd_fees),
+        Amount(t.eur_fees.0),
+        Text(t.four_week_rule_applied.to_string()),
+        Text(t.source_file.clone()),
+        Text(t.account.clone()),
+        Text(t.excluded.to_string()),
+        Text(t.development_land.to_string()),
+    ])
+}
+
+#[cfg(feature = "io")]
+/// Writes the CSV detail to a file, see [`write_detail`].
+pub fn write_detail_as_csv<P: AsRef<Path>>(
+    transactions: &[Transaction],
+    file_path: P,
+) -> Result<()> {
+    write_detail(transactions, File::create(file_path)?)
+}
+
+#[cfg(feature = "io")]
+/// Writes the CSV detail of the transactions, a header row then one row per
+/// transaction, to any writer, e.g. a buffer or the standard output.
+pub fn write_detail<W: io::Write>(transactions: &[Transaction], writer: W) -> Result<()> {
+    let mut wtr = csv::Writer::from_writer(writer);
+    wtr.write_record(DETAIL_HEADERS)?;
+    for t in transactions {
+        wtr.write_record(detail_record(t)?.iter().map(ToString::to_string))?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+/// Label of a row of the summary of a report, and the amount of a period it shows.
+type SummaryRow = (String, fn(&PeriodTaxReport) -> Decimal);
+
+/// Amounts of the periods shown by the summaries of a report, the "Summary" sheet of
+/// the workbook and the Markdown tables.
+fn summary_rows(currency: &str) -> [SummaryRow; 11] {
+    [
+        (format!("Total proceeds ({})", currency), |p| p.usd_proceeds),
+        (format!("Total gain ({})", currency), |p| p.usd_gain),
+        (format!("Total loss ({})", currency), |p| p.usd_loss),
+        (format!("Net gain ({})", currency), |p| p.usd_net_gain),
+        ("Total proceeds (EUR)".to_string(), |p| p.eur_proceeds.0),
+        ("Total gain (EUR)".to_string(), |p| p.eur_gain.0),
+        ("Total loss (EUR)".to_string(), |p| p.eur_loss.0),
+        ("Net gain (EUR)".to_string(), |p| p.eur_net_gain.0),
+        ("Exemption available (EUR)".to_string(), |p| {
+            p.eur_allowance.0
+        }),
+        ("Net chargeable gain (EUR)".to_string(), |p| {
+            p.eur_taxable_gain.0
+        }),
+        ("Tax to pay (EUR)".to_string(), |p| p.eur_tax.0),
+    ]
+}
+
+/// Writes a workbook with a "Summary" sheet of the figures of the payment periods and
+/// of the year, and a "Detail" sheet with the same columns as the CSV detail.
+#[cfg(feature = "io")]
+pub fn write_report_as_xlsx<P: AsRef<Path>>(
+    report: &TaxReport,
+    transactions: &[Transaction],
+    file_path: P,
+) -> Result<()> {
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    let bold = rust_xlsxwriter::Format::new().set_bold();
+    let cents = rust_xlsxwriter::Format::new().set_num_format("0.00");
+
+    let summary = workbook.add_worksheet().set_name("Summary")?;
+    let periods: Vec<&PeriodTaxReport> = report
+        .payment_periods
+        .iter()
+        .chain([&report.period_tax_report])
+        .collect();
+    summary.write_string_with_format(0, 0, format!("Fiscal year {}", report.fiscal_year), &bold)?;
+    for (col, period) in (1..).zip(&periods) {
+        let label = period.label.as_deref().unwrap_or("entire fiscal year");
+        summary.write_string_with_format(0, col, label, &bold)?;
+        if let Some((start, end)) = period.period {
+            let dates = format!(
+                "{} to {}",
+                start.format(EXR_API_DATE_FMT)?,
+                end.format(EXR_API_DATE_FMT)?
+            );
+            summary.write_string(1, col, dates)?;
+        }
+        if let Some(due_date) = period.due_date {
+            summary.write_string(2, col, due_date.format(EXR_API_DATE_FMT)?)?;
+        }
+        summary.write_number(3, col, period.transaction_count as f64)?;
+    }
+    for (row, label) in (1..).zip(["Period", "Due date", "Disposals"]) {
+        summary.write_string(row, 0, label)?;
+    }
+    let mut row = 4;
+    for (label, amount) in summary_rows(&report.currency) {
+        summary.write_string(row, 0, label)?;
+        for (col, period) in (1..).zip(&periods) {
+            summary.write_number_with_format(
+                row,
+                col,
+                to_f64(round_to_cents(amount(period))),
+                &cents,
+            )?;
+        }
+        row += 1;
+    }
+    // After a blank row
+    row += 1;
+    summary.write_string(row, 0, "Loss carried forward from previous years (EUR)")?;
+    summary.write_number_with_format(row, 1, to_f64(report.loss_carried_forward_in.0), &cents)?;
+    summary.write_string(row + 1, 0, "Loss carried forward to next year (EUR)")?;
+    summary.write_number_with_format(
+        row + 1,
+        1,
+        to_f64(report.loss_carried_forward_out.0),
+        &cents,
+    )?;
+    summary.autofit();
+
+    let detail = workbook.add_worksheet().set_name("Detail")?;
+    for (col, header) in (0..).zip(DETAIL_HEADERS) {
+        detail.write_string_with_format(0, col, header, &bold)?;
+    }
+    for (row, t) in (1..).zip(transactions) {
+        for (col, field) in (0..).zip(detail_record(t)?) {
+            match field {
+                DetailField::Text(text) => detail.write_string(row, col, text)?,
+                DetailField::Amount(amount) => detail.write_number(row, col, to_f64(amount))?,
+            };
+        }
+    }
+    detail.autofit();
+
+    workbook.save(file_path)?;
+    Ok(())
+}
+
+#[cfg(feature = "io")]
+fn to_f64(amount: Decimal) -> f64 {
+    use rust_decimal::prelude::ToPrimitive;
+    amount.to_f64().unwrap_or_default()
+}
+
+#[cfg(feature = "io")]
+pub fn write_report_as_json<P: AsRef<Path>>(report: &TaxReport, file_path: P) -> Result<()> {
+    serde_json::to_writer_pretty(File::create(file_path)?, report)?;
+    Ok(())
+}
+
+/// Writes the figures of the payment periods and of the year as GitHub-flavoured
+/// Markdown tables, with one column per period as in the "Summary" sheet of
+/// [`write_report_as_xlsx`], e.g. to paste the report into notes.
+pub fn write_report_as_markdown<W: io::Write>(report: &TaxReport, mut writer: W) -> Result<()> {
+    let periods: Vec<&PeriodTaxReport> = report
+        .payment_periods
+        .iter()
+        .chain([&report.period_tax_report])
+        .collect();
+    writeln!(writer, "## Fiscal year {}\n", report.fiscal_year)?;
+    let labels: Vec<&str> = (periods.iter())
+        .map(|period| period.label.as_deref().unwrap_or("entire fiscal year"))
+        .collect();
+    writeln!(writer, "| | {} |", labels.join(" | "))?;
+    writeln!(writer, "|---|{}", "---:|".repeat(periods.len()))?;
+
+    let mut dates = Vec::with_capacity(periods.len());
+    let mut due_dates = Vec::with_capacity(periods.len());
+    for period in &periods {
+        dates.push(match period.period {
+            Some((start, end)) => format!(
+                "{} to {}",
+                start.format(EXR_API_DATE_FMT)?,
+                end.format(EXR_API_DATE_FMT)?
+            ),
+            None => String::new(),
+        });
+        due_dates.push(match period.due_date {
+            Some(due_date) => due_date.format(EXR_API_DATE_FMT)?,
+            None => String::new(),
+        });
+    }
+    writeln!(writer, "| Period | {} |", dates.join(" | "))?;
+    writeln!(writer, "| Due date | {} |", due_dates.join(" | "))?;
+    let counts: Vec<String> = (periods.iter())
+        .map(|period| period.transaction_count.to_string())
+        .collect();
+    writeln!(writer, "| Disposals | {} |", counts.join(" | "))?;
+    for (label, amount) in summary_rows(&report.currency) {
+        let amounts: Vec<String> = (periods.iter())
+            .map(|period| format_money(amount(period)))
+            .collect();
+        writeln!(writer, "| {} | {} |", label, amounts.join(" | "))?;
+    }
+
+    writeln!(writer)?;
+    writeln!(
+        writer,
+        "- Loss carried forward from previous years: €{}",
+        report.loss_carried_forward_in.formatted()
+    )?;
+    writeln!(
+        writer,
+        "- Loss carried forward to next year: €{}",
+        report.loss_carried_forward_out.formatted()
+    )?;
+    writeln!(
+        writer,
+        "- CGT return required: {}",
+        if report.return_required { "yes" } else { "no" }
+    )?;
+    Ok(())
+}
+
+pub fn print_report_as_json(report: &impl Serialize) -> Result<()> {
+    serde_json::to_writer_pretty(io::stdout().lock(), report)?;
+    println!();
+    Ok(())
+}
+
+/// Builds a separate report for each fiscal year covered by the transactions, in order.
+/// The losses carried forward of the parameters are those carried into the first year,
+/// and each later year starts from the losses left by the previous one, so that a loss
+/// is only deducted once.
+pub fn build_reports_by_year(
+    transactions: &[Transaction],
+    params: &TaxParams,
+) -> Result<Vec<TaxReport>> {
+    if transactions.is_empty() {
+        return Err(CgtError::NoDisposals);
+    }
+    let mut loss_forward = params.loss_carried_forward_eur;
+    let mut reports = Vec::new();
+    for year_transactions in group_by_year(transactions.to_vec()).values() {
+        let year_params = TaxParams {
+            loss_carried_forward_eur: loss_forward,
+            ..*params
+        };
+        let report = build_report(year_transactions, &year_params)?;
+        loss_forward = report.loss_carried_forward_out;
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// Prints a separate report for each fiscal year covered by the transactions (see
+/// [`build_reports_by_year`]).
+pub fn compute_and_print_report(transactions: &[Transaction], params: &TaxParams) -> Result<()> {
+    for report in build_reports_by_year(transactions, params)? {
+        print_report(&report, params)?;
+    }
+    Ok(())
+}
+
+pub fn print_report(report: &TaxReport, params: &TaxParams) -> Result<()> {
+    print_report_basis(report)?;
+    for period_report in &report.payment_periods {
+        if let Some(period) = period_report.period {
+            print_period_header(period, period_report.label.as_deref())?;
+        }
+        print_period_report(period_report, &report.currency, params);
+    }
+
+    // Full year
+    println!(
+        "\n=== TAX REPORT FOR ENTIRE FISCAL YEAR {} ===\n",
+        report.fiscal_year
+    );
+    print_period_report(&report.period_tax_report, &report.currency, params);
+    println!(
+        "Effective tax rate on gains: {:.2}% (statutory rate {:.2}%)",
+        round_to_cents(report.effective_rate * Decimal::ONE_HUNDRED),
+        params.rate * Decimal::ONE_HUNDRED
+    );
+    if report.eur_exemption_used > Eur::ZERO {
+        println!(
+            "\nExemption already used elsewhere: €{}",
+            report.eur_exemption_used.formatted()
+        );
+        println!(
+            "Exemption remaining for these disposals: €{}",
+            report.eur_exemption.formatted()
+        );
+    }
+    if report.loss_carried_forward_in > Eur::ZERO || report.loss_carried_forward_out > Eur::ZERO {
+        println!(
+            "\nLoss carried forward from previous years: €{}",
+            report.loss_carried_forward_in.formatted()
+        );
+        println!(
+            "Loss carried forward to next year: €{}",
+            report.loss_carried_forward_out.formatted()
+        );
+    }
+    if report.return_required {
+        println!(
+            "\nA CGT return is required: the proceeds exceed €{}",
+            params.return_threshold_eur.formatted()
+        );
+    } else {
+        println!(
+            "\nNo CGT return is required for the proceeds: they do not exceed €{}",
+            params.return_threshold_eur.formatted()
+        );
+    }
+
+    println!("\n=== PAYMENT DEADLINES ===\n");
+    for period_report in &report.payment_periods {
+        if let Some(due_date) = period_report.due_date {
+            println!(
+                "€{} due by {}",
+                period_report.eur_tax.formatted(),
+                due_date.format(DEADLINE_DATE_FMT)?
+            );
+        }
+    }
+    let adjustment = report.eur_tax_adjustment.round_to_cents();
+    if adjustment < Eur::ZERO {
+        println!(
+            "€{} paid for the earlier periods to reclaim with the annual return, as the losses of the later periods reduce the tax of the year",
+            (-adjustment).formatted()
+        );
+    }
+    Ok(())
+}
+
+/// Aggregates the transactions of each symbol, leaving out the excluded ones. The
+/// exemption is not applied, and transactions read without a symbol are grouped
+/// under an empty one.
+pub fn compute_per_asset_report(transactions: &[Transaction]) -> HashMap<String, PeriodTaxReport> {
+    let mut by_symbol: HashMap<&str, Vec<Transaction>> = HashMap::new();
+    for t in transactions.iter().filter(|t| !t.excluded) {
+        by_symbol.entry(&t.symbol).or_default().push(t.clone());
+    }
+    by_symbol
+        .into_iter()
+        .map(|(symbol, transactions)| {
+            let report = compute_period_report(
+                &transactions,
+                None,
+                (Eur::ZERO, Eur::ZERO),
+                (Decimal::ZERO, Decimal::ZERO),
+            );
+            (symbol.to_string(), report)
+        })
+        .collect()
+}
+
+/// Prints the net gain and the proceeds of each symbol, from the largest gain to the
+/// largest loss.
+pub fn print_per_asset_report(transactions: &[Transaction]) {
+    let mut reports: Vec<_> = compute_per_asset_report(transactions).into_iter().collect();
+    reports.sort_by_key(|(_, report)| std::cmp::Reverse(report.eur_net_gain));
+    println!("\n=== GAIN/LOSS PER ASSET ===\n");
+    for (symbol, report) in reports {
+        let symbol = if symbol.is_empty() {
+            "(no symbol)"
+        } else {
+            &symbol
+        };
+        println!(
+            "{}: net gain €{}, proceeds €{}",
+            symbol,
+            report.eur_net_gain.formatted(),
+            report.eur_proceeds.formatted()
+        );
+    }
+}
+
+/// Aggregates the transactions of each month of their fiscal year, leaving out the
+/// excluded ones, e.g. to follow the gains realised before the end of the initial
+/// period. The exemption is not applied, and months without disposals are zero.
+pub fn compute_monthly_report(transactions: &[Transaction]) -> Result<[PeriodTaxReport; 12]> {
+    let mut reports: [PeriodTaxReport; 12] = Default::default();
+    let Some(fiscal_year) = transactions.first().map(|t| t.sell_date.year()) else {
+        return Ok(reports);
+    };
+    let mut month = Month::January;
+    for report in &mut reports {
+        let start = Date::from_calendar_date(fiscal_year, month, 1)?;
+        let end = Date::from_calendar_date(fiscal_year, month, month.length(fiscal_year))?;
+        *report = compute_period_report(
+            transactions,
+            Some((start, end)),
+            (Eur::ZERO, Eur::ZERO),
+            (Decimal::ZERO, Decimal::ZERO),
+        );
+        month = month.next();
+    }
+    Ok(reports)
+}
+
+/// Projects the report of the whole year from the report of the initial period
+/// (January to November) and an expected net gain (negative for a loss) in December,
+/// to estimate the tax of the year before it ends. The exemption is the allowance of
+/// the initial period, which is the whole allowance of the year; the USD amounts and
+/// the proceeds are those of the initial period only.
+pub fn project_december(
+    initial: &PeriodTaxReport,
+    projected_december_gain: Eur,
+    rate: Decimal,
+) -> Result<PeriodTaxReport> {
+    let eur_gain = initial.eur_gain + Eur::max(projected_december_gain, Eur::ZERO);
+    let eur_loss = initial.eur_loss + Eur::max(-projected_december_gain, Eur::ZERO);
+    let eur_net_gain = eur_gain - eur_loss;
+    let eur_taxable_gain = Eur::max(eur_net_gain - initial.eur_allowance, Eur::ZERO);
+    let period = match initial.period {
+        Some((start, _)) => Some((
+            start,
+            Date::from_calendar_date(start.year(), Month::December, 31)?,
+        )),
+        None => None,
+    };
+    Ok(PeriodTaxReport {
+        period,
+        due_date: None,
+        label: Some("projected year".to_string()),
+        eur_gain,
+        eur_loss,
+        eur_net_gain,
+        eur_taxable_gain,
+        eur_tax: eur_taxable_gain * rate,
+        ..initial.clone()
+    })
+}
+
+/// Prints the tax due for the initial period and the projected tax of the year with
+/// the given net gain in December, as computed by [`project_december`].
+pub fn print_december_projection(
+    report: &TaxReport,
+    projected_december_gain: Eur,
+    params: &TaxParams,
+) -> Result<()> {
+    let Some(initial) = report.payment_periods.first() else {
+        return Ok(());
+    };
+    let projection = project_december(initial, projected_december_gain, params.rate)?;
+    println!(
+        "\n=== PRELIMINARY TAX FOR {} WITH A PROJECTED DECEMBER ===\n",
+        report.fiscal_year
+    );
+    println!(
+        "Projected net gain in December: €{}",
+        projected_december_gain.formatted()
+    );
+    println!(
+        "Projected net gain of the year: €{}",
+        projection.eur_net_gain.formatted()
+    );
+    println!(
+        "Projected net chargeable gain (amount above exemption): €{}",
+        projection.eur_taxable_gain.formatted()
+    );
+    let due_by = |period: Option<&PeriodTaxReport>| -> Result<String> {
+        match period.and_then(|p| p.due_date) {
+            Some(due_date) => Ok(format!(" by {}", due_date.format(EXR_API_DATE_FMT)?)),
+            None => Ok(String::new()),
+        }
+    };
     println!(
-        "\nNet chargeable gain (amount above exemption): €{:.2}",
-        report.eur_chargeable_gain
+        "Tax due{} for the initial period: €{}",
+        due_by(Some(initial))?,
+        initial.eur_tax.formatted()
     );
     println!(
-        "Tax to pay ({:.2}%): €{:.2}",
-        TAX_RATE * 100.,
-        report.eur_tax
+        "Projected tax due{} for December: €{}",
+        due_by(report.payment_periods.get(1))?,
+        Eur::max(projection.eur_tax - initial.eur_tax, Eur::ZERO).formatted()
+    );
+    println!(
+        "Projected tax of the year ({:.2}%): €{}",
+        params.rate * Decimal::ONE_HUNDRED,
+        projection.eur_tax.formatted()
+    );
+    Ok(())
+}
+
+/// Prints the net gain of each month, along with the net gain of the year so far.
+pub fn print_monthly_report(transactions: &[Transaction]) -> Result<()> {
+    println!("\n=== NET GAIN PER MONTH ===\n");
+    let mut cumulative = Eur::ZERO;
+    let mut month = Month::January;
+    for report in compute_monthly_report(transactions)? {
+        cumulative += report.eur_net_gain;
+        println!(
+            "{}: net gain €{} (year to date €{})",
+            month,
+            report.eur_net_gain.formatted(),
+            cumulative.formatted()
+        );
+        month = month.next();
+    }
+    Ok(())
+}
+
+/// Transactions with the largest gain and the largest loss in EUR, leaving out the
+/// excluded ones, `None` when there is no gain (or no loss). The earliest transaction
+/// is picked among equal ones.
+pub fn largest_gain_and_loss(
+    transactions: &[Transaction],
+) -> (Option<&Transaction>, Option<&Transaction>) {
+    let included = || transactions.iter().filter(|t| !t.excluded);
+    let largest_gain = included()
+        .filter(|t| t.eur_gain > Eur::ZERO)
+        .min_by_key(|t| std::cmp::Reverse(t.eur_gain));
+    let largest_loss = included()
+        .filter(|t| t.eur_loss > Eur::ZERO)
+        .min_by_key(|t| std::cmp::Reverse(t.eur_loss));
+    (largest_gain, largest_loss)
+}
+
+/// Prints the transactions with the largest gain and the largest loss, which helps
+/// spotting a wrong row.
+pub fn print_largest_gain_and_loss(transactions: &[Transaction]) {
+    let (largest_gain, largest_loss) = largest_gain_and_loss(transactions);
+    if largest_gain.is_none() && largest_loss.is_none() {
+        return;
+    }
+    println!("\n=== LARGEST GAIN AND LOSS ===\n");
+    let describe = |t: &Transaction| {
+        let symbol = if t.symbol.is_empty() {
+            "(no symbol)"
+        } else {
+            &t.symbol
+        };
+        format!("{} on {}", symbol, t.sell_date)
+    };
+    if let Some(t) = largest_gain {
+        println!(
+            "Largest gain: {} ({})",
+            describe(t),
+            color::gain(format!("+€{}", t.eur_gain.formatted()))
+        );
+    }
+    if let Some(t) = largest_loss {
+        println!(
+            "Largest loss: {} ({})",
+            describe(t),
+            color::loss(format!("-€{}", t.eur_loss.formatted()))
+        );
+    }
+}
+
+/// Prints the figures of the full year in the order and with the labels of the
+/// capital gains panel of Revenue's Form CG1, so that they can be transcribed as-is.
+pub fn print_cg1_summary(report: &TaxReport) {
+    let year = &report.period_tax_report;
+    let net_gain = Eur::max(year.eur_net_gain, Eur::ZERO);
+    let losses_used = Eur::min(report.loss_carried_forward_in, net_gain);
+    let exemption = Eur::min(
+        year.eur_allowance - report.loss_carried_forward_in,
+        net_gain - losses_used,
+    );
+    println!("\n=== FORM CG1 SUMMARY FOR {} ===\n", report.fiscal_year);
+    let lines = [
+        ("Aggregate consideration", year.eur_proceeds),
+        ("Chargeable gains", year.eur_gain),
+        ("Allowable losses in the year", year.eur_loss),
+        ("Net chargeable gain", net_gain),
+        ("Losses carried forward from prior years", losses_used),
+        ("Personal exemption", exemption),
+        ("Net chargeable gain after exemption", year.eur_taxable_gain),
+        ("Tax due", year.eur_tax),
+        (
+            "Losses to carry forward to next year",
+            report.loss_carried_forward_out,
+        ),
+    ];
+    for (label, amount) in lines {
+        println!("{}: €{}", label, amount.formatted());
+    }
+}
+
+/// Prints a report built by [`build_custom_period_report`], whose amounts are in the
+/// given currency.
+pub fn print_custom_period_report(
+    report: &PeriodTaxReport,
+    currency: &str,
+    params: &TaxParams,
+) -> Result<()> {
+    if let Some(period) = report.period {
+        print_period_header(period, None)?;
+    }
+    print_period_report(report, currency, params);
+    Ok(())
+}
+
+fn print_period_header(period: (Date, Date), label: Option<&str>) -> Result<()> {
+    print!(
+        "\n=== TAX REPORT FOR PERIOD {} TO {}",
+        period.0.format(EXR_API_DATE_FMT)?,
+        period.1.format(EXR_API_DATE_FMT)?
     );
+    match label {
+        Some(label) => println!(" ({}) ===\n", label),
+        None => println!(" ===\n"),
+    }
+    Ok(())
+}
+
+fn currency_symbol(currency: &str) -> &str {
+    match currency {
+        "USD" => "$",
+        "GBP" => "£",
+        "EUR" => "€",
+        _ => currency,
+    }
+}
+
+fn print_period_report(report: &PeriodTaxReport, currency: &str, params: &TaxParams) {
+    let sym = currency_symbol(currency);
+    let money = |amount: Decimal| format!("{}{}", sym, format_money(amount));
+    let euros = |amount: Eur| format!("€{}", amount.formatted());
+    // Groups of lines, printed with a blank line between them
+    let mut groups = vec![
+        vec![
+            (
+                "Disposals".to_string(),
+                report.transaction_count.to_string(),
+                Paint::Plain,
+            ),
+            (
+                format!("Total proceeds ({})", currency),
+                money(report.usd_proceeds),
+                Paint::Plain,
+            ),
+            (
+                format!("Total gain ({})", currency),
+                money(report.usd_gain),
+                Paint::Gain,
+            ),
+            (
+                format!("Total loss ({})", currency),
+                money(report.usd_loss),
+                Paint::Loss,
+            ),
+            (
+                format!("Net gain ({})", currency),
+                money(report.usd_net_gain),
+                Paint::Net(report.usd_net_gain),
+            ),
+        ],
+        vec![
+            (
+                "Total proceeds".to_string(),
+                euros(report.eur_proceeds),
+                Paint::Plain,
+            ),
+            (
+                "Total gain".to_string(),
+                euros(report.eur_gain),
+                Paint::Gain,
+            ),
+            (
+                "Total loss".to_string(),
+                euros(report.eur_loss),
+                Paint::Loss,
+            ),
+            (
+                "Net gain (Gain-Loss)".to_string(),
+                euros(report.eur_net_gain),
+                Paint::Net(report.eur_net_gain.0),
+            ),
+        ],
+    ];
+    let mut tax = vec![
+        (
+            "Exemption available".to_string(),
+            euros(report.eur_allowance),
+            Paint::Plain,
+        ),
+        (
+            "Net chargeable gain (amount above exemption)".to_string(),
+            euros(report.eur_taxable_gain),
+            Paint::Plain,
+        ),
+    ];
+    if report.eur_development_land_taxable_gain > Eur::ZERO {
+        tax.push((
+            "Of which on development land".to_string(),
+            euros(report.eur_development_land_taxable_gain),
+            Paint::Plain,
+        ));
+    }
+    tax.push((
+        format!("Tax to pay ({:.2}%)", params.rate * Decimal::ONE_HUNDRED),
+        euros(report.eur_tax),
+        Paint::Tax,
+    ));
+    if report.eur_development_land_tax > Eur::ZERO {
+        tax.push((
+            format!(
+                "Of which on development land ({:.2}%)",
+                params.development_land_rate * Decimal::ONE_HUNDRED
+            ),
+            euros(report.eur_development_land_tax),
+            Paint::Plain,
+        ));
+    }
+    groups.push(tax);
+
+    // The labels and the amounts are aligned across the groups, the amounts to the
+    // right so that their decimal points line up
+    let lines = groups.iter().flatten();
+    let label_width = lines.clone().map(|(label, _, _)| label.chars().count());
+    let label_width = label_width.max().unwrap_or_default() + 1;
+    let amount_width = lines.map(|(_, amount, _)| amount.chars().count());
+    let amount_width = amount_width.max().unwrap_or_default();
+    for (i, group) in groups.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        for (label, amount, paint) in group {
+            println!(
+                "{:<label_width$} {}",
+                format!("{}:", label),
+                paint.apply(format!("{:>amount_width$}", amount))
+            );
+        }
+    }
 }
@@ -1,20 +1,787 @@
-use std::env;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    process::{self, ExitCode, Stdio},
+    sync::Arc,
+    time::Duration,
+};
 
 use capital_gain_tax_ireland::{
-    compute_and_print_report, get_transactions, write_detail_as_csv, Result,
+    broker::{Degiro, ETrade, InteractiveBrokers},
+    build_custom_period_report, build_pooled_report, build_report, check_file,
+    fifo::get_transactions_fifo_from_files,
+    get_transactions_from_files_by_year, get_transactions_from_reader_by_year, group_by_year,
+    list_sheets, parse_iso_date, print_cg1_summary, print_custom_period_report,
+    print_december_projection, print_largest_gain_and_loss, print_lifetime_summary,
+    print_monthly_report, print_per_asset_report, print_report, print_report_as_json, set_colors,
+    statutory_rate_change, summarise_years, usd_gain_reconciliation, write_detail_as_csv,
+    write_report_as_markdown, write_report_as_xlsx, CgtError, DateFormat, Eur, RateStrategy,
+    ReadParams, RoundMode, TaxParams, Transaction,
 };
 
-use anyhow::Error;
+use anyhow::{bail, Context, Result};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use time::Date;
+
+const DEFAULT_DETAIL_PATH: &str = "CGT_transaction_detail.csv";
+/// Configuration file read from the current directory when `--config` is not given.
+const DEFAULT_CONFIG_PATH: &str = "cgt.toml";
+
+/// Exit code of a report with tax to pay, so that scripts can tell it apart.
+const EXIT_TAX_DUE: u8 = 3;
+/// Exit code of the errors reading the input files.
+const EXIT_INPUT_ERROR: u8 = 4;
+/// Exit code of the errors getting the exchange rates (e.g. the ECB is unreachable).
+const EXIT_RATE_ERROR: u8 = 5;
+/// Exit code of the other errors.
+const EXIT_ERROR: u8 = 1;
+
+const EXIT_CODES_HELP: &str = "\
+Exit codes:
+  0  success, and no tax due
+  1  error
+  2  invalid command line
+  3  success, and tax due
+  4  error reading the input files
+  5  error getting the exchange rates";
+
+/// Computes the Irish Capital Gains Tax due on the sales of an ETrade "Gains & Losses" export.
+#[derive(Parser)]
+#[command(
+    name = "cgt",
+    version,
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true,
+    after_help = EXIT_CODES_HELP
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    report: ReportArgs,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the tax report of each fiscal year (default)
+    Report(ReportArgs),
+    /// Only write the CSV detail of the transactions
+    Detail(DetailArgs),
+    /// List the sheets of spreadsheets, e.g. to find the one to pass to --sheet
+    Sheets(SheetsArgs),
+}
+
+/// Defaults of the options, read from a TOML file. The options given on the command
+/// line take precedence.
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Config {
+    rate: Option<Decimal>,
+    exemption: Option<Decimal>,
+    return_threshold: Option<Decimal>,
+    development_land_rate: Option<Decimal>,
+    sheet: Option<String>,
+    from_currency: Option<String>,
+    to_currency: Option<String>,
+    /// Path of the CSV detail, for both the `report` and `detail` subcommands.
+    detail_out: Option<PathBuf>,
+    rates: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads the given configuration file, or `cgt.toml` if there is one.
+    fn load(file_path: Option<&Path>) -> Result<Self> {
+        let file_path = match file_path {
+            Some(file_path) => file_path,
+            None if Path::new(DEFAULT_CONFIG_PATH).exists() => Path::new(DEFAULT_CONFIG_PATH),
+            None => return Ok(Self::default()),
+        };
+        let content = fs::read_to_string(file_path)
+            .with_context(|| format!("failed to read config file {}", file_path.display()))?;
+        toml::from_str(&content)
+            .with_context(|| format!("invalid config file {}", file_path.display()))
+    }
+}
+
+#[derive(Args)]
+struct InputArgs {
+    /// Excel (or CSV) files to read, one per brokerage account
+    #[arg(required_unless_present = "stdin")]
+    files: Vec<PathBuf>,
+    /// Read the transactions as CSV from the standard input instead of from files
+    #[arg(long, conflicts_with_all = ["files", "fifo", "pool_exemptions"])]
+    stdin: bool,
+    /// TOML file with the defaults of the options [default: cgt.toml, if it exists]
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Recompute the gains by matching the sales against the purchases (FIFO)
+    #[arg(long)]
+    fifo: bool,
+    /// Only report (and write the detail of) this fiscal year
+    #[arg(long)]
+    year: Option<i32>,
+    /// Report spouses together, passing one file per spouse taxed against its own exemption
+    /// but sharing the losses
+    #[arg(long)]
+    pool_exemptions: bool,
+    /// Broker the files were exported from
+    #[arg(long, value_enum, default_value_t = Broker::Etrade)]
+    broker: Broker,
+    /// Name of the sheet to read when the spreadsheet has several [default: G&L_Expanded]
+    #[arg(long)]
+    sheet: Option<String>,
+    /// Currency of the amounts of the spreadsheet [default: USD]
+    #[arg(long)]
+    from_currency: Option<String>,
+    /// Currency to convert the amounts to, only EUR is supported [default: EUR]
+    #[arg(long)]
+    to_currency: Option<String>,
+    /// Number of retries of the failed requests to the ECB
+    #[arg(long, default_value_t = 3)]
+    max_retries: u32,
+    /// Maximum number of requests sent to the ECB at the same time, 1 to send them one by one
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u16).range(1..))]
+    fetch_jobs: u16,
+    /// Timeout of the requests to the ECB, in seconds
+    #[arg(long, default_value_t = 10)]
+    timeout: u64,
+    /// Keep the full precision of the converted amounts instead of rounding them to the cent
+    #[arg(long)]
+    no_rounding: bool,
+    /// How the converted amounts are rounded to the cent
+    #[arg(long, value_enum, default_value_t = RoundModeArg::HalfUp, conflicts_with = "no_rounding")]
+    round_mode: RoundModeArg,
+    /// Print every exchange rate lookup and request
+    #[arg(long)]
+    verbose: bool,
+    /// CSV file of `date,rate` rows overriding the ECB rates
+    #[arg(long)]
+    rates: Option<PathBuf>,
+    /// Fetch the ECB rates of the dates of the rates file too, and use them where they differ
+    #[arg(long)]
+    refresh_rates: bool,
+    /// CSV file to append every rate fetched from the ECB to, with the URL of its request
+    #[arg(long, value_name = "PATH")]
+    audit_log: Option<PathBuf>,
+    /// Exchange rate to convert each transaction at
+    #[arg(long, value_enum, default_value_t = RateStrategyArg::Daily)]
+    rate_strategy: RateStrategyArg,
+    /// Average rate of a year, as YEAR=RATE, instead of the ECB's with
+    /// `--rate-strategy yearly-average` (can be repeated)
+    #[arg(long, value_name = "YEAR=RATE", value_parser = parse_yearly_rate)]
+    yearly_rate: Vec<(i32, Decimal)>,
+    /// Format of the dates of the files [default: the format of the broker]
+    #[arg(long, value_enum)]
+    date_format: Option<DateFormatArg>,
+    /// Date the sales by their "Date Sold" column even when the file has a "Trade Date" column
+    #[arg(long)]
+    use_settlement_date: bool,
+    /// Leave the transactions of this symbol out of the reports (can be repeated)
+    #[arg(long, value_name = "SYMBOL")]
+    exclude_symbol: Vec<String>,
+    /// Leave the transactions of this account out of the reports (can be repeated)
+    #[arg(long, value_name = "ACCOUNT")]
+    exclude_account: Vec<String>,
+    /// Leave the disposals before this day (YYYY-MM-DD) out of the reports, e.g. in the
+    /// year of arrival in Ireland
+    #[arg(long, value_name = "DATE", value_parser = parse_iso_date)]
+    resident_from: Option<Date>,
+    /// Leave the disposals after this day (YYYY-MM-DD) out of the reports, e.g. in the
+    /// year of departure from Ireland
+    #[arg(long, value_name = "DATE", value_parser = parse_iso_date)]
+    resident_until: Option<Date>,
+    /// Tax the disposals of this symbol as disposals of development land (can be repeated)
+    #[arg(long, value_name = "SYMBOL")]
+    development_land: Vec<String>,
+    /// Do not check that the gain/loss of each sale matches its proceeds minus its cost basis
+    #[arg(long)]
+    no_cost_check: bool,
+    /// Fail on the first row that cannot be parsed instead of skipping it with a warning
+    #[arg(long)]
+    strict: bool,
+    /// Remove the sales identical in date, symbol, proceeds and gain/loss to an earlier one
+    #[arg(long)]
+    dedupe: bool,
+}
+
+impl InputArgs {
+    fn read_params(&self, config: &Config) -> ReadParams {
+        let defaults = ReadParams::default();
+        ReadParams {
+            from_currency: (self.from_currency.clone())
+                .or_else(|| config.from_currency.clone())
+                .unwrap_or(defaults.from_currency),
+            to_currency: (self.to_currency.clone())
+                .or_else(|| config.to_currency.clone())
+                .unwrap_or(defaults.to_currency),
+            max_retries: self.max_retries,
+            fetch_jobs: self.fetch_jobs.into(),
+            timeout: Duration::from_secs(self.timeout),
+            sheet: (self.sheet.clone())
+                .or_else(|| config.sheet.clone())
+                .unwrap_or(defaults.sheet),
+            round_to_cents: !self.no_rounding,
+            verbose: self.verbose,
+            rates_file: self.rates.clone().or_else(|| config.rates.clone()),
+            refresh_rates: self.refresh_rates,
+            audit_log: self.audit_log.clone(),
+            check_cost_basis: !self.no_cost_check,
+            dedupe: self.dedupe,
+            strict: self.strict,
+            use_trade_date: !self.use_settlement_date,
+            broker_format: match self.broker {
+                Broker::Etrade => Arc::new(ETrade),
+                Broker::Ibkr => Arc::new(InteractiveBrokers),
+                Broker::Degiro => Arc::new(Degiro),
+            },
+            exclude_symbols: self.exclude_symbol.clone(),
+            exclude_accounts: self.exclude_account.clone(),
+            resident_from: self.resident_from,
+            resident_until: self.resident_until,
+            development_land_symbols: self.development_land.clone(),
+            date_format: match self.date_format {
+                None => DateFormat::Broker,
+                Some(DateFormatArg::Mdy) => DateFormat::MonthDayYear,
+                Some(DateFormatArg::Dmy) => DateFormat::DayMonthYear,
+                Some(DateFormatArg::Ymd) => DateFormat::YearMonthDay,
+                Some(DateFormatArg::Auto) => DateFormat::Auto,
+            },
+            rate_strategy: match self.rate_strategy {
+                RateStrategyArg::Daily => RateStrategy::Daily,
+                RateStrategyArg::YearlyAverage => RateStrategy::YearlyAverage,
+                RateStrategyArg::Interpolated => RateStrategy::Interpolated,
+            },
+            yearly_rates: self.yearly_rate.iter().copied().collect(),
+            round_mode: match self.round_mode {
+                RoundModeArg::HalfUp => RoundMode::HalfUp,
+                RoundModeArg::HalfEven => RoundMode::HalfEven,
+            },
+            ..ReadParams::default()
+        }
+    }
+}
+
+#[derive(Args)]
+struct ReportArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// CGT rate [default: the statutory rate of the disposals, 0.33 since 6 December 2012]
+    #[arg(long)]
+    rate: Option<Decimal>,
+    /// Annual personal exemption, in EUR [default: 1270]
+    #[arg(long)]
+    exemption: Option<Decimal>,
+    /// Part of the annual exemption already used by gains taxed elsewhere, in EUR (for
+    /// a single fiscal year, see --year)
+    #[arg(long, default_value = "0")]
+    exemption_used: Decimal,
+    /// Unused losses from the years before the first one reported, in EUR
+    #[arg(long, default_value = "0")]
+    loss_forward: Decimal,
+    /// Total proceeds above which a CGT return is required, in EUR [default: 2540]
+    #[arg(long)]
+    return_threshold: Option<Decimal>,
+    /// CGT rate of the gains on development land [default: the CGT rate]
+    #[arg(long)]
+    development_land_rate: Option<Decimal>,
+    /// Output format of the report
+    #[arg(long, value_enum, default_value_t = Format::Text)]
+    format: Format,
+    /// Path of the CSV detail of the transactions [default: CGT_transaction_detail.csv]
+    #[arg(long)]
+    detail_out: Option<PathBuf>,
+    /// Do not write the CSV detail of the transactions
+    #[arg(long, conflicts_with = "detail_out")]
+    no_detail: bool,
+    /// Also print the figures to report on Form CG1 (text format only)
+    #[arg(long)]
+    cg1: bool,
+    /// Also print the gain/loss of each asset (text format only)
+    #[arg(long)]
+    per_asset: bool,
+    /// Also print the net gain of each month (text format only)
+    #[arg(long)]
+    monthly: bool,
+    /// Also estimate the tax of the year with this net gain in December, in EUR
+    /// (text format only)
+    #[arg(long, allow_negative_numbers = true)]
+    project_december: Option<Decimal>,
+    /// Compare the net gain of all the fiscal years, in USD, with this total of the
+    /// broker's statement
+    #[arg(long, allow_negative_numbers = true, value_name = "AMOUNT")]
+    expected_usd_gain: Option<Decimal>,
+    /// Only report the disposals from this date, as YYYY-MM-DD, with the exemption of its
+    /// fiscal year (requires --to)
+    #[arg(long, value_name = "DATE", value_parser = parse_iso_date, requires = "to", conflicts_with = "pool_exemptions")]
+    from: Option<Date>,
+    /// Only report the disposals up to this date, as YYYY-MM-DD (requires --from)
+    #[arg(long, value_name = "DATE", value_parser = parse_iso_date, requires = "from")]
+    to: Option<Date>,
+    /// Only print the tax of each fiscal year, as a bare number
+    #[arg(long, conflicts_with = "format")]
+    quiet: bool,
+    /// Open the CSV detail (and the workbooks with --format xlsx) once written, with the
+    /// default application of the desktop
+    #[arg(long)]
+    open: bool,
+    /// Do not colour the gains, the losses and the tax (neither does a NO_COLOR
+    /// environment variable, nor output that is not a terminal)
+    #[arg(long)]
+    no_color: bool,
+    /// Only check that the files can be parsed, without fetching any exchange rate
+    #[arg(long, conflicts_with = "stdin")]
+    check: bool,
+}
+
+#[derive(Args)]
+struct DetailArgs {
+    #[command(flatten)]
+    input: InputArgs,
+    /// Path of the CSV file to write [default: CGT_transaction_detail.csv]
+    #[arg(long)]
+    out: Option<PathBuf>,
+    /// Open the CSV file once written, with the default application of the desktop
+    #[arg(long)]
+    open: bool,
+}
+
+#[derive(Args)]
+struct SheetsArgs {
+    /// Excel files to list the sheets of
+    #[arg(required = true)]
+    files: Vec<PathBuf>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Broker {
+    Etrade,
+    Ibkr,
+    Degiro,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum DateFormatArg {
+    /// MM/DD/YYYY
+    Mdy,
+    /// DD/MM/YYYY
+    Dmy,
+    /// YYYY-MM-DD
+    Ymd,
+    /// Detect the format, and fail if the dates can be read in several formats
+    Auto,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RoundModeArg {
+    /// Halves away from zero, as in Revenue's examples
+    HalfUp,
+    /// Halves to the even cent (banker's rounding)
+    HalfEven,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum RateStrategyArg {
+    /// The ECB rate of the day of each transaction
+    Daily,
+    /// The average of the ECB rates of the year of each transaction
+    YearlyAverage,
+    /// The ECB rate of the day of each transaction, interpolated between the surrounding
+    /// business days for a day without one
+    Interpolated,
+}
+
+fn parse_yearly_rate(arg: &str) -> Result<(i32, Decimal)> {
+    let (year, rate) = arg.split_once('=').context("expected a YEAR=RATE pair")?;
+    let rate: Decimal = rate.trim().parse().context("invalid rate")?;
+    // The amounts are divided by the rate
+    if rate <= Decimal::ZERO {
+        bail!("the rate must be positive");
+    }
+    Ok((year.trim().parse().context("invalid year")?, rate))
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Format {
+    Text,
+    Json,
+    /// One workbook per fiscal year, with a summary and a detail sheet
+    Xlsx,
+    /// Markdown tables of the figures of each fiscal year
+    #[value(name = "md")]
+    Markdown,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    let tax_due = match cli.command {
+        Some(Command::Report(args)) => report(&args),
+        Some(Command::Detail(args)) => detail(&args).map(|()| false),
+        Some(Command::Sheets(args)) => sheets(&args).map(|()| false),
+        None => report(&cli.report),
+    };
+    match tax_due {
+        Ok(false) => ExitCode::SUCCESS,
+        Ok(true) => ExitCode::from(EXIT_TAX_DUE),
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            ExitCode::from(exit_code(&error))
+        }
+    }
+}
+
+/// Exit code of an error, from the first [`CgtError`] of its chain of causes.
+fn exit_code(error: &anyhow::Error) -> u8 {
+    let Some(error) = error.chain().find_map(|e| e.downcast_ref::<CgtError>()) else {
+        return EXIT_ERROR;
+    };
+    match error {
+        CgtError::MissingSheet { .. }
+        | CgtError::MissingHeader(_)
+        | CgtError::MixedFiscalYears
+        | CgtError::NoDisposals
+        | CgtError::MissingYear { .. }
+        | CgtError::UnknownDateFormat
+        | CgtError::AmbiguousDateFormat(_)
+        | CgtError::WrongDateFormat(_)
+        | CgtError::InvalidQuantity { .. }
+        | CgtError::UnsupportedTargetCurrency(_)
+        | CgtError::Csv(_)
+        | CgtError::Xlsx(_)
+        | CgtError::DateParse(_) => EXIT_INPUT_ERROR,
+        CgtError::ExchangeRateUnavailable(_)
+        | CgtError::CurrencyRateUnavailable(..)
+        | CgtError::InvalidExchangeRate(..)
+        | CgtError::YearlyRateUnavailable(_)
+        | CgtError::RateParse(_)
+        | CgtError::Http(_) => EXIT_RATE_ERROR,
+        _ => EXIT_ERROR,
+    }
+}
+
+/// Prints (or writes) the report of each fiscal year, and returns whether tax is due.
+fn report(args: &ReportArgs) -> Result<bool> {
+    let config = Config::load(args.input.config.as_deref())?;
+    if args.check {
+        return check(&args.input, &config).map(|()| false);
+    }
+    set_colors(use_colors(args));
+    let transaction_sets = read_transaction_sets(&args.input, &config)?;
+    if !args.no_detail {
+        let detail_out = detail_path(args.detail_out.as_ref(), &config);
+        write_detail_as_csv(&flatten(&transaction_sets), &detail_out)?;
+        // Keep the standard output parseable in JSON, and ready to paste in Markdown
+        if !args.quiet && !matches!(args.format, Format::Json | Format::Markdown) {
+            print_detail_written(&detail_out);
+        }
+        if args.open && !args.quiet {
+            open_file(&detail_out);
+        }
+    }
+
+    if let (Some(from), Some(to)) = (args.from, args.to) {
+        return report_period(args, &config, &flatten(&transaction_sets), (from, to));
+    }
+
+    let years: BTreeSet<i32> = transaction_sets
+        .iter()
+        .flat_map(|set| set.keys().copied())
+        .collect();
+    if years.is_empty() {
+        return Err(CgtError::NoDisposals.into());
+    }
+    if years.len() > 1 && !args.exemption_used.is_zero() {
+        bail!("the exemption used elsewhere is that of a single fiscal year, pass it with --year");
+    }
+    let mut reports = Vec::with_capacity(years.len());
+    // The losses given are those carried into the first year, and each later year
+    // starts from the losses left by the previous one
+    let mut loss_forward = Eur(args.loss_forward);
+    for year in years {
+        let year_sets: Vec<_> = transaction_sets
+            .iter()
+            .map(|set| set.get(&year).cloned().unwrap_or_default())
+            .collect();
+        let params = TaxParams {
+            loss_carried_forward_eur: loss_forward,
+            ..tax_params(args, &config, year, &year_sets.concat())
+        };
+        let report = if args.input.pool_exemptions {
+            build_pooled_report(&year_sets, &params)?
+        } else {
+            build_report(&year_sets[0], &params)?
+        };
+        match args.format {
+            _ if args.quiet => println!("{:.2}", report.period_tax_report.eur_tax.round_to_cents()),
+            Format::Text => {
+                print_report(&report, &params)?;
+                print_largest_gain_and_loss(&year_sets.concat());
+                if args.cg1 {
+                    print_cg1_summary(&report);
+                }
+                if args.per_asset {
+                    print_per_asset_report(&year_sets.concat());
+                }
+                if args.monthly {
+                    print_monthly_report(&year_sets.concat())?;
+                }
+                if let Some(gain) = args.project_december {
+                    print_december_projection(&report, Eur(gain), &params)?;
+                }
+            }
+            Format::Json => print_report_as_json(&report)?,
+            Format::Xlsx => {
+                let file_path = format!("CGT_report_{}.xlsx", year);
+                write_report_as_xlsx(&report, &year_sets.concat(), &file_path)?;
+                println!("The report of {} was written to file {}", year, file_path);
+                if args.open {
+                    open_file(Path::new(&file_path));
+                }
+            }
+            Format::Markdown => {
+                if !reports.is_empty() {
+                    println!();
+                }
+                write_report_as_markdown(&report, io::stdout().lock())?;
+            }
+        }
+        loss_forward = report.loss_carried_forward_out;
+        reports.push(report);
+    }
+    if reports.len() > 1 && !args.quiet && matches!(args.format, Format::Text) {
+        print_lifetime_summary(&summarise_years(&reports));
+    }
+    if let Some(expected) = args.expected_usd_gain {
+        let reconciliation = usd_gain_reconciliation(&reports, expected);
+        // Keep the standard output parseable in the other formats
+        if !args.quiet && matches!(args.format, Format::Text) {
+            println!("\n{}", reconciliation);
+        } else {
+            eprintln!("{}", reconciliation);
+        }
+    }
+
+    Ok(reports
+        .iter()
+        .any(|r| r.period_tax_report.eur_tax > Eur::ZERO))
+}
 
-fn main() -> Result<()> {
-    let args: Vec<String> = env::args().collect();
-    if args.len() != 2 {
-        return Err(Error::msg("Usage: ./cgt \"path/to/file.xlsx\""));
+/// Prints the report of the disposals of a custom period, and returns whether tax is due.
+fn report_period(
+    args: &ReportArgs,
+    config: &Config,
+    transactions: &[Transaction],
+    period: (Date, Date),
+) -> Result<bool> {
+    let in_period: Vec<Transaction> = (transactions.iter())
+        .filter(|t| (period.0..=period.1).contains(&t.sell_date()))
+        .cloned()
+        .collect();
+    let params = tax_params(args, config, period.0.year(), &in_period);
+    let report = build_custom_period_report(transactions, &params, period)?;
+    match args.format {
+        _ if args.quiet => println!("{:.2}", report.eur_tax.round_to_cents()),
+        Format::Text => {
+            let currency = transactions.first().map_or("", Transaction::currency);
+            print_custom_period_report(&report, currency, &params)?;
+        }
+        Format::Json => print_report_as_json(&report)?,
+        Format::Xlsx | Format::Markdown => {
+            bail!("the report of a custom period can only be printed as text or JSON")
+        }
     }
+    Ok(report.eur_tax > Eur::ZERO)
+}
 
-    let transactions = get_transactions(&args[1])?;
-    write_detail_as_csv(&transactions, "CGT_transaction_detail.csv")?;
-    compute_and_print_report(&transactions)?;
+/// Parameters of the tax of the fiscal year: those given, or the statutory ones of the
+/// disposals of the year.
+fn tax_params(
+    args: &ReportArgs,
+    config: &Config,
+    year: i32,
+    transactions: &[Transaction],
+) -> TaxParams {
+    let defaults = TaxParams::for_disposals(year, transactions);
+    let rate = args.rate.or(config.rate);
+    if let (None, Some(change)) = (rate, statutory_rate_change(transactions)) {
+        eprintln!(
+            "Warning: the CGT rate changed on {} between the disposals of {}, which are all taxed at {}%, pass --rate to change it",
+            change,
+            year,
+            defaults.rate * Decimal::ONE_HUNDRED
+        );
+    }
+    TaxParams {
+        rate: rate.unwrap_or(defaults.rate),
+        exemption_eur: (args.exemption.or(config.exemption))
+            .map(Eur)
+            .unwrap_or(defaults.exemption_eur),
+        exemption_used_eur: Eur(args.exemption_used),
+        loss_carried_forward_eur: Eur(args.loss_forward),
+        return_threshold_eur: (args.return_threshold.or(config.return_threshold))
+            .map(Eur)
+            .unwrap_or(defaults.return_threshold_eur),
+        development_land_rate: (args.development_land_rate.or(config.development_land_rate))
+            .unwrap_or(defaults.development_land_rate),
+    }
+}
 
+/// Whether to colour the text report, which is only done for a terminal, and never
+/// when the `NO_COLOR` environment variable is set to a non-empty value.
+fn use_colors(args: &ReportArgs) -> bool {
+    !args.no_color
+        && !args.quiet
+        && matches!(args.format, Format::Text)
+        && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+        && io::stdout().is_terminal()
+}
+
+fn check(args: &InputArgs, config: &Config) -> Result<()> {
+    let read_params = args.read_params(config);
+    for file_path in &args.files {
+        let count = check_file(file_path, &read_params)
+            .with_context(|| format!("failed to parse {}", file_path.display()))?;
+        println!(
+            "{}: {} sales parsed successfully",
+            file_path.display(),
+            count
+        );
+    }
     Ok(())
 }
+
+fn detail(args: &DetailArgs) -> Result<()> {
+    let config = Config::load(args.input.config.as_deref())?;
+    let transaction_sets = read_transaction_sets(&args.input, &config)?;
+    let out = detail_path(args.out.as_ref(), &config);
+    write_detail_as_csv(&flatten(&transaction_sets), &out)?;
+    print_detail_written(&out);
+    if args.open {
+        open_file(&out);
+    }
+    Ok(())
+}
+
+fn sheets(args: &SheetsArgs) -> Result<()> {
+    for file_path in &args.files {
+        let sheet_names = list_sheets(file_path)?;
+        if args.files.len() > 1 {
+            println!("{}:", file_path.display());
+        }
+        for name in sheet_names {
+            println!("{}", name);
+        }
+    }
+    Ok(())
+}
+
+fn detail_path(arg: Option<&PathBuf>, config: &Config) -> PathBuf {
+    arg.or(config.detail_out.as_ref())
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_DETAIL_PATH))
+}
+
+fn print_detail_written(file_path: &Path) {
+    println!(
+        "The transaction detail was written as CSV to file {}",
+        file_path.display()
+    );
+}
+
+/// Opens the file with the default application of the desktop, and does nothing
+/// without a desktop (e.g. over SSH) as there is no application to open it with.
+fn open_file(file_path: &Path) {
+    let mut command = if cfg!(target_os = "macos") {
+        process::Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = process::Command::new("cmd");
+        // The empty title keeps a quoted path from being taken for the title
+        command.args(["/C", "start", ""]);
+        command
+    } else if std::env::var_os("DISPLAY").is_some() || std::env::var_os("WAYLAND_DISPLAY").is_some()
+    {
+        process::Command::new("xdg-open")
+    } else {
+        return;
+    };
+    let opened = command
+        .arg(file_path)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn();
+    if let Err(error) = opened {
+        eprintln!(
+            "Warning: could not open file {}: {}",
+            file_path.display(),
+            error
+        );
+    }
+}
+
+/// Reads one set of transactions per person: each file belongs to a different person
+/// when exemptions are pooled, otherwise all files are accounts of the same person.
+fn read_transaction_sets(
+    args: &InputArgs,
+    config: &Config,
+) -> Result<Vec<BTreeMap<i32, Vec<Transaction>>>> {
+    let read_params = args.read_params(config);
+    let mut transaction_sets = if args.stdin {
+        vec![get_transactions_from_reader_by_year(
+            io::stdin().lock(),
+            &read_params,
+        )?]
+    } else if args.pool_exemptions {
+        args.files
+            .iter()
+            .map(|file_path| read_transactions(&[file_path], args.fifo, &read_params))
+            .collect::<Result<Vec<_>>>()?
+    } else {
+        vec![read_transactions(&args.files, args.fifo, &read_params)?]
+    };
+    if let Some(year) = args.year {
+        let available: BTreeSet<i32> = transaction_sets
+            .iter()
+            .flat_map(|set| set.keys().copied())
+            .collect();
+        if !available.contains(&year) {
+            return Err(CgtError::MissingYear {
+                year,
+                available: available.into_iter().collect(),
+            }
+            .into());
+        }
+        for set in &mut transaction_sets {
+            set.retain(|set_year, _| *set_year == year);
+        }
+    }
+    Ok(transaction_sets)
+}
+
+fn read_transactions<P: AsRef<Path>>(
+    file_paths: &[P],
+    fifo: bool,
+    read_params: &ReadParams,
+) -> Result<BTreeMap<i32, Vec<Transaction>>> {
+    if fifo {
+        Ok(group_by_year(get_transactions_fifo_from_files(
+            file_paths,
+            read_params,
+        )?))
+    } else {
+        Ok(get_transactions_from_files_by_year(
+            file_paths,
+            read_params,
+        )?)
+    }
+}
+
+fn flatten(transaction_sets: &[BTreeMap<i32, Vec<Transaction>>]) -> Vec<Transaction> {
+    transaction_sets
+        .iter()
+        .flat_map(|set| set.values().flatten().cloned())
+        .collect()
+}
@@ -0,0 +1,160 @@
+//! Amounts in euros, kept apart from the amounts in the currency of the account so
+//! that the compiler rejects mixing them up (e.g. deducting the exemption from a
+//! dollar figure). Euro amounts only come from converting another currency with
+//! [`Eur::convert`], or from the tax parameters.
+
+use std::{
+    fmt,
+    iter::Sum,
+    ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign},
+};
+
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Eur(pub Decimal);
+
+impl Eur {
+    pub const ZERO: Eur = Eur(Decimal::ZERO);
+
+    /// Converts an amount with the exchange rate of one euro in its currency.
+    ///
+    /// # Panics
+    ///
+    /// If `exr` is not positive. The rates read by the crate are checked when parsed,
+    /// and those given to it are rejected with [`CgtError::InvalidExchangeRate`].
+    ///
+    /// [`CgtError::InvalidExchangeRate`]: crate::CgtError::InvalidExchangeRate
+    pub fn convert(amount: Decimal, exr: Decimal) -> Self {
+        assert!(
+            exr > Decimal::ZERO,
+            "the exchange rate {exr} is not positive"
+        );
+        Eur(amount / exr)
+    }
+
+    pub fn is_zero(self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn abs(self) -> Self {
+        Eur(self.0.abs())
+    }
+
+    pub fn round_to_cents(self) -> Self {
+        self.round_to_cents_with(RoundMode::HalfUp)
+    }
+
+    pub fn round_to_cents_with(self, mode: RoundMode) -> Self {
+        Eur(self.0.round_dp_with_strategy(2, mode.strategy()))
+    }
+
+    /// Formats the amount for reading, see [`format_money`].
+    pub fn formatted(self) -> String {
+        format_money(self.0)
+    }
+}
+
+/// Formats an amount rounded to the cent, with a comma between the groups of thousands
+/// (e.g. `-1,234,567.80`), for the printed reports. The CSV and JSON outputs keep the
+/// amounts as numbers.
+pub fn format_money(amount: Decimal) -> String {
+    let rounded = amount.round_dp_with_strategy(2, RoundingStrategy::MidpointAwayFromZero);
+    let digits = format!("{:.2}", rounded.abs());
+    let (units, cents) = digits.split_once('.').unwrap_or((&digits, "00"));
+    let mut grouped = String::with_capacity(units.len() + units.len() / 3);
+    for (i, digit) in units.chars().enumerate() {
+        if i > 0 && (units.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if rounded < Decimal::ZERO { "-" } else { "" };
+    format!("{}{}.{}", sign, grouped, cents)
+}
+
+/// How the amounts are rounded to the cent when they are halfway between two cents.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RoundMode {
+    /// Away from zero (€0.125 gives €0.13), as in Revenue's examples.
+    #[default]
+    HalfUp,
+    /// To the even cent (€0.125 gives €0.12), the banker's rounding.
+    HalfEven,
+}
+
+impl RoundMode {
+    fn strategy(self) -> RoundingStrategy {
+        match self {
+            RoundMode::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+            RoundMode::HalfEven => RoundingStrategy::MidpointNearestEven,
+        }
+    }
+}
+
+impl fmt::Display for Eur {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+impl Add for Eur {
+    type Output = Eur;
+
+    fn add(self, other: Eur) -> Eur {
+        Eur(self.0 + other.0)
+    }
+}
+
+impl AddAssign for Eur {
+    fn add_assign(&mut self, other: Eur) {
+        self.0 += other.0;
+    }
+}
+
+impl Sub for Eur {
+    type Output = Eur;
+
+    fn sub(self, other: Eur) -> Eur {
+        Eur(self.0 - other.0)
+    }
+}
+
+impl SubAssign for Eur {
+    fn sub_assign(&mut self, other: Eur) {
+        self.0 -= other.0;
+    }
+}
+
+impl Neg for Eur {
+    type Output = Eur;
+
+    fn neg(self) -> Eur {
+        Eur(-self.0)
+    }
+}
+
+/// Applies a rate, e.g. the CGT rate to a chargeable gain.
+impl Mul<Decimal> for Eur {
+    type Output = Eur;
+
+    fn mul(self, rate: Decimal) -> Eur {
+        Eur(self.0 * rate)
+    }
+}
+
+/// Ratio of two amounts, e.g. the effective tax rate.
+impl Div for Eur {
+    type Output = Decimal;
+
+    fn div(self, other: Eur) -> Decimal {
+        self.0 / other.0
+    }
+}
+
+impl Sum for Eur {
+    fn sum<I: Iterator<Item = Eur>>(iter: I) -> Eur {
+        iter.fold(Eur::ZERO, Add::add)
+    }
+}
@@ -0,0 +1,25 @@
+use capital_gain_tax_ireland::broker::{BrokerFormat, ETrade, InteractiveBrokers};
+
+#[test]
+fn etrade_sale_records_ignore_case_and_whitespace() {
+    for record_type in [
+        "Sell",
+        "SELL",
+        "sell",
+        " Sell",
+        "Sell ",
+        "\tbuy to cover",
+        "BUY TO COVER",
+    ] {
+        assert!(ETrade.is_sale_record(record_type), "{:?}", record_type);
+    }
+    for record_type in ["Buy", "Summary", "", "Sells", "Short Sell"] {
+        assert!(!ETrade.is_sale_record(record_type), "{:?}", record_type);
+    }
+}
+
+#[test]
+fn interactive_brokers_sale_records_ignore_case_and_whitespace() {
+    assert!(InteractiveBrokers.is_sale_record(" sell "));
+    assert!(!InteractiveBrokers.is_sale_record("BUY"));
+}
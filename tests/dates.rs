@@ -0,0 +1,94 @@
+//! Checks of the detection of the format of the dates of the input files.
+
+#![cfg(feature = "io")]
+
+use std::path::PathBuf;
+
+use capital_gain_tax_ireland::{check_file, get_transactions, CgtError, DateFormat, ReadParams};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn params(date_format: DateFormat) -> ReadParams {
+    ReadParams {
+        date_format,
+        ..ReadParams::default()
+    }
+}
+
+#[test]
+fn european_dates_are_detected() {
+    let file = fixture("european_dates.csv");
+    assert!(check_file(&file, &params(DateFormat::Broker)).is_err());
+    assert_eq!(check_file(&file, &params(DateFormat::Auto)).unwrap(), 3);
+    assert_eq!(
+        check_file(&file, &params(DateFormat::DayMonthYear)).unwrap(),
+        3
+    );
+}
+
+#[test]
+fn ambiguous_dates_are_rejected() {
+    let file = fixture("ambiguous_dates.csv");
+    let error = check_file(&file, &params(DateFormat::Auto)).unwrap_err();
+    assert!(
+        matches!(&error, CgtError::AmbiguousDateFormat(date) if date == "03/04/2023"),
+        "{:?}",
+        error
+    );
+    assert_eq!(
+        check_file(&file, &params(DateFormat::MonthDayYear)).unwrap(),
+        2
+    );
+}
+
+#[test]
+fn us_dates_are_detected() {
+    let file = fixture("gains_and_losses.xlsx");
+    assert_eq!(check_file(&file, &params(DateFormat::Auto)).unwrap(), 3);
+}
+
+#[test]
+fn date_cells_are_read_as_dates() {
+    let file_path = std::env::temp_dir().join("cgt_date_cells.xlsx");
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet().set_name("G&L_Expanded").unwrap();
+    let headers = [
+        "Record Type",
+        "Symbol",
+        "Date Sold",
+        "Total Proceeds",
+        "Adjusted Gain/Loss",
+    ];
+    for (col, header) in (0..).zip(headers) {
+        sheet.write_string(0, col, header).unwrap();
+    }
+    let date_format = Format::new().set_num_format("dd/mm/yyyy");
+    let sales = [((2023, 3, 15), "ACME"), ((2023, 12, 5), "INIT")];
+    for (row, ((year, month, day), symbol)) in (1..).zip(sales) {
+        sheet.write_string(row, 0, "Sell").unwrap();
+        sheet.write_string(row, 1, symbol).unwrap();
+        let date = ExcelDateTime::from_ymd(year, month, day).unwrap();
+        sheet
+            .write_datetime_with_format(row, 2, &date, &date_format)
+            .unwrap();
+        sheet.write_number(row, 3, 1000).unwrap();
+        sheet.write_number(row, 4, 100).unwrap();
+    }
+    workbook.save(&file_path).unwrap();
+
+    let read_params = ReadParams {
+        rates_file: Some(fixture("rates.csv")),
+        ..params(DateFormat::Auto)
+    };
+    let transactions = get_transactions(&file_path, &read_params).unwrap();
+    let sell_dates: Vec<_> = transactions
+        .iter()
+        .map(|t| serde_json::to_value(t).unwrap()["sell_date"].clone())
+        .collect();
+    assert_eq!(sell_dates, ["2023-03-15", "2023-12-05"]);
+}
@@ -0,0 +1,313 @@
+//! Checks of the fetching and parsing of the ECB rates, against a local server
+//! standing in for the ECB API.
+
+#![cfg(feature = "io")]
+
+use std::{
+    fs,
+    io::{BufRead, BufReader, Write},
+    net::TcpListener,
+    path::PathBuf,
+    thread,
+};
+
+use capital_gain_tax_ireland::{
+    build_report, get_transactions, get_transactions_from_reader_by_year, write_detail, CgtError,
+    RateStrategy, ReadParams, Result, TaxParams, Transaction,
+};
+
+const HEADER: &str = "KEY,FREQ,CURRENCY,CURRENCY_DENOM,EXR_TYPE,EXR_SUFFIX,TIME_PERIOD,OBS_VALUE\n";
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// Answers every request with the given CSV, and returns the base URL to query.
+fn serve(body: String) -> String {
+    serve_with(move |_| body.clone())
+}
+
+/// Answers every request with the CSV returned for its request line.
+fn serve_with(answer: impl Fn(&str) -> String + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(&stream);
+            let mut request = String::new();
+            reader.read_line(&mut request).unwrap();
+            let body = answer(&request);
+            let mut line = String::new();
+            // Skip the headers, up to the blank line
+            while reader.read_line(&mut line).unwrap() > 2 {
+                line.clear();
+            }
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: text/csv\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+    url
+}
+
+/// Reads the fixture with the rates answered by the server.
+fn read_fixture(body: String) -> Result<Vec<Transaction>> {
+    read_fixture_with(body, ReadParams::default())
+}
+
+/// Same as [`read_fixture`] with other parameters.
+fn read_fixture_with(body: String, params: ReadParams) -> Result<Vec<Transaction>> {
+    let read_params = ReadParams {
+        exr_base_url: Some(serve(body)),
+        max_retries: 0,
+        ..params
+    };
+    get_transactions(fixture("gains_and_losses.xlsx"), &read_params)
+}
+
+#[test]
+fn fetched_rates_give_the_golden_report() {
+    let body = [
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-15,1.0598",
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-06-20,1.0922",
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-12-05,1.0808",
+    ]
+    .join("\n");
+    let transactions = read_fixture(format!("{}{}\n", HEADER, body)).unwrap();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    let expected: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(fixture("expected_report.json")).unwrap())
+            .unwrap();
+    assert_eq!(serde_json::to_value(&report).unwrap(), expected);
+}
+
+#[test]
+fn missing_rates_are_reported() {
+    let error = read_fixture(HEADER.to_string()).unwrap_err();
+    assert!(
+        matches!(error, CgtError::ExchangeRateUnavailable(_)),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn malformed_rates_are_reported() {
+    // The amounts are divided by the rates, which cannot be zero or negative either
+    for rate in ["1.05.98", "0", "-1.0598"] {
+        let body = format!(
+            "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-15,{}\n",
+            rate
+        );
+        let error = read_fixture(format!("{}{}", HEADER, body)).unwrap_err();
+        assert!(matches!(error, CgtError::RateParse(_)), "{:?}", error);
+    }
+}
+
+#[test]
+fn yearly_average_converts_the_whole_year_at_one_rate() {
+    let body = "EXR.A.USD.EUR.SP00.A,A,USD,EUR,SP00,A,2023,1.0813\n";
+    let params = ReadParams {
+        rate_strategy: RateStrategy::YearlyAverage,
+        ..ReadParams::default()
+    };
+    let transactions = read_fixture_with(format!("{}{}", HEADER, body), params).unwrap();
+    for t in &transactions {
+        let t = serde_json::to_value(t).unwrap();
+        assert_eq!(t["exr"], 1.0813);
+        assert_eq!(t["exr_date"], "2023-12-31");
+        assert_eq!(t["exr_series"], "A.USD.EUR.SP00.A");
+    }
+
+    let error = read_fixture_with(
+        HEADER.to_string(),
+        ReadParams {
+            rate_strategy: RateStrategy::YearlyAverage,
+            ..ReadParams::default()
+        },
+    )
+    .unwrap_err();
+    assert!(
+        matches!(error, CgtError::YearlyRateUnavailable(2023)),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn years_are_fetched_concurrently() {
+    let body = [
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2022-03-15,1.0982",
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-12-05,1.0808",
+    ]
+    .join("\n");
+    let url = serve(format!("{}{}\n", HEADER, body));
+    let sales = "Record Type,Date Sold,Adjusted Gain/Loss,Total Proceeds\n\
+        Sell,03/15/2022,1098.2,5000\n\
+        Sell,12/05/2023,-108.08,1000\n";
+    let read = |fetch_jobs| {
+        let params = ReadParams {
+            exr_base_url: Some(url.clone()),
+            max_retries: 0,
+            fetch_jobs,
+            ..ReadParams::default()
+        };
+        get_transactions_from_reader_by_year(sales.as_bytes(), &params).unwrap()
+    };
+
+    let by_year = read(8);
+    assert_eq!(by_year[&2022][0].exr(), "1.0982".parse().unwrap());
+    assert_eq!(by_year[&2023][0].exr(), "1.0808".parse().unwrap());
+    let serially = read(1);
+    for (year, transactions) in &by_year {
+        assert_eq!(
+            transactions[0].eur_proceeds(),
+            serially[year][0].eur_proceeds()
+        );
+    }
+}
+
+#[test]
+fn rows_are_converted_at_the_rate_of_their_currency() {
+    let url = serve_with(|request| {
+        let body = if request.contains("/D.GBP.EUR.SP00.A?") {
+            "EXR.D.GBP.EUR.SP00.A,D,GBP,EUR,SP00,A,2023-03-15,0.8797\n"
+        } else if request.contains("/D.USD.EUR.SP00.A?") {
+            "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-15,1.0598\n"
+        } else {
+            ""
+        };
+        format!("{}{}", HEADER, body)
+    });
+    let sales = "Record Type,Date Sold,Adjusted Gain/Loss,Total Proceeds,Currency\n\
+        Sell,03/15/2023,105.98,1000,USD\n\
+        Sell,03/15/2023,87.97,1000,GBP\n\
+        Sell,03/15/2023,-50,1000,EUR\n";
+    let params = ReadParams {
+        exr_base_url: Some(url),
+        max_retries: 0,
+        ..ReadParams::default()
+    };
+    let transactions =
+        &get_transactions_from_reader_by_year(sales.as_bytes(), &params).unwrap()[&2023];
+
+    let currencies: Vec<&str> = transactions.iter().map(Transaction::currency).collect();
+    assert_eq!(currencies, ["USD", "GBP", "EUR"]);
+    assert_eq!(transactions[0].eur_gain(), transactions[1].eur_gain());
+    assert_eq!(transactions[1].exr_series(), "D.GBP.EUR.SP00.A");
+    assert_eq!(transactions[2].exr(), "1".parse().unwrap());
+    assert_eq!(transactions[2].exr_series(), "");
+
+    let sales = sales.replace("GBP", "XYZ");
+    let error = get_transactions_from_reader_by_year(sales.as_bytes(), &params).unwrap_err();
+    assert!(
+        matches!(&error, CgtError::CurrencyRateUnavailable(currency, _) if currency == "XYZ"),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn fetched_rates_are_appended_to_the_audit_log() {
+    let audit_log = std::env::temp_dir().join("cgt_audit_log.csv");
+    let _ = fs::remove_file(&audit_log);
+    let body = "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-15,\"1,0598\"\n";
+    let params = ReadParams {
+        audit_log: Some(audit_log.clone()),
+        ..ReadParams::default()
+    };
+    read_fixture_with(format!("{}{}", HEADER, body), params.clone()).unwrap();
+    read_fixture_with(format!("{}{}", HEADER, body), params).unwrap();
+
+    let log = fs::read_to_string(&audit_log).unwrap();
+    let lines: Vec<&str> = log.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Timestamp,Start Period,End Period,Observation Period,URL,OBS_VALUE,Rate"
+    );
+    // The headers are only written once, and every row keeps the raw value
+    assert_eq!(
+        lines.iter().filter(|l| l.starts_with("Timestamp")).count(),
+        1
+    );
+    let row: Vec<&str> = lines[1].split(',').collect();
+    assert_eq!(row[3], "2023-03-15");
+    assert!(row[4].contains("/D.USD.EUR.SP00.A?"), "{}", row[4]);
+    assert!(lines[1].ends_with("\"1,0598\",1.0598"), "{}", lines[1]);
+}
+
+#[test]
+fn weekend_rates_are_interpolated_between_the_surrounding_days() {
+    let body = [
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-17,1.0600",
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-20,1.0660",
+    ]
+    .join("\n");
+    let sales = "Record Type,Date Sold,Adjusted Gain/Loss,Total Proceeds\n\
+        Sell,03/17/2023,106,1060\n\
+        Sell,03/18/2023,106,1062\n\
+        Sell,03/19/2023,106,1064\n";
+    let params = ReadParams {
+        exr_base_url: Some(serve(format!("{}{}\n", HEADER, body))),
+        max_retries: 0,
+        rate_strategy: RateStrategy::Interpolated,
+        ..ReadParams::default()
+    };
+    let by_year = get_transactions_from_reader_by_year(sales.as_bytes(), &params).unwrap();
+
+    let expected = [
+        (1.06, "2023-03-17", "D.USD.EUR.SP00.A"),
+        (1.062, "2023-03-18", "D.USD.EUR.SP00.A (interpolated)"),
+        (1.064, "2023-03-19", "D.USD.EUR.SP00.A (interpolated)"),
+    ];
+    for (t, (exr, exr_date, exr_series)) in by_year[&2023].iter().zip(expected) {
+        let t = serde_json::to_value(t).unwrap();
+        assert_eq!(t["exr"], exr);
+        assert_eq!(t["exr_date"], exr_date);
+        assert_eq!(t["exr_series"], exr_series);
+    }
+}
+
+#[test]
+fn rates_keep_the_precision_published_by_the_ecb() {
+    let body = [
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-17,1.0810",
+        "EXR.D.USD.EUR.SP00.A,D,USD,EUR,SP00,A,2023-03-20,1.0723",
+    ]
+    .join("\n");
+    let sales = "Record Type,Date Sold,Adjusted Gain/Loss,Total Proceeds\n\
+        Sell,03/17/2023,108,1081\n\
+        Sell,03/18/2023,107,1072\n";
+    let params = ReadParams {
+        exr_base_url: Some(serve(format!("{}{}\n", HEADER, body))),
+        max_retries: 0,
+        rate_strategy: RateStrategy::Interpolated,
+        ..ReadParams::default()
+    };
+    let by_year = get_transactions_from_reader_by_year(sales.as_bytes(), &params).unwrap();
+    let mut detail = Vec::new();
+    write_detail(&by_year[&2023], &mut detail).unwrap();
+
+    // The rates are written as published, trailing zero included, and the interpolated
+    // ones to the same number of decimals
+    let mut rdr = csv::Reader::from_reader(detail.as_slice());
+    let exr_index = rdr
+        .headers()
+        .unwrap()
+        .iter()
+        .position(|h| h == "EXR")
+        .unwrap();
+    let rates: Vec<String> = rdr
+        .records()
+        .map(|r| r.unwrap()[exr_index].to_string())
+        .collect();
+    assert_eq!(rates, ["1.0810", "1.0781"]);
+}
@@ -0,0 +1,163 @@
+//! Checks of the matching of disposals against acquisitions.
+
+use capital_gain_tax_ireland::{
+    fifo::{apply_four_week_rule, match_disposals, Acquisition, Disposal},
+    CgtError, Eur,
+};
+use rust_decimal::Decimal;
+use time::macros::date;
+
+fn amount(text: &str) -> Decimal {
+    text.parse().unwrap()
+}
+
+#[test]
+fn cost_is_converted_at_the_acquisition_rate() {
+    let acquisitions = [Acquisition {
+        symbol: "ACME".to_string(),
+        date: date!(2022 - 01 - 10),
+        quantity: amount("10"),
+        usd_cost: amount("1000"),
+    }];
+    let disposals = [Disposal {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 06 - 01),
+        quantity: amount("10"),
+        usd_proceeds: amount("1050"),
+        usd_reported_gain_loss: amount("50"),
+        source_file: String::new(),
+        account: String::new(),
+    }];
+    // The dollar gained 20% on the euro while the shares were held
+    let transactions = match_disposals(&acquisitions, &disposals, |date| {
+        let exr = if date.year() == 2022 { "1" } else { "1.2" };
+        Ok((amount(exr), date))
+    })
+    .unwrap();
+
+    // The gain in dollars is a loss in euros: €1000 of cost for €875 of proceeds
+    let t = &transactions[0];
+    assert_eq!(t.usd_gain(), amount("50"));
+    assert_eq!(t.eur_proceeds(), Eur(amount("875")));
+    assert_eq!(t.eur_gain(), Eur::ZERO);
+    assert_eq!(t.eur_loss(), Eur(amount("125")));
+}
+
+#[test]
+fn shares_bought_on_the_day_are_matched_first() {
+    let lot = |date, usd_cost: &str| Acquisition {
+        symbol: "ACME".to_string(),
+        date,
+        quantity: amount("10"),
+        usd_cost: amount(usd_cost),
+    };
+    let acquisitions = [
+        lot(date!(2022 - 01 - 10), "500"),
+        lot(date!(2023 - 05 - 20), "900"),
+        lot(date!(2023 - 06 - 01), "1000"),
+    ];
+    let disposal = |quantity: &str| Disposal {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 06 - 01),
+        quantity: amount(quantity),
+        usd_proceeds: amount("1050"),
+        usd_reported_gain_loss: amount("50"),
+        source_file: String::new(),
+        account: String::new(),
+    };
+    let transactions = match_disposals(&acquisitions, &[disposal("10"), disposal("10")], |date| {
+        Ok((Decimal::ONE, date))
+    })
+    .unwrap();
+
+    // The shares bought on the day, then those bought in the four weeks before, and
+    // the oldest ones held only after them
+    assert_eq!(transactions[0].usd_gain(), amount("50"));
+    assert!(!transactions[0].four_week_rule_applied());
+    assert_eq!(transactions[1].usd_gain(), amount("150"));
+    assert!(transactions[1].four_week_rule_applied());
+}
+
+#[test]
+fn acquisitions_without_shares_are_rejected() {
+    let acquisitions = [Acquisition {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 01 - 10),
+        quantity: Decimal::ZERO,
+        usd_cost: amount("1000"),
+    }];
+    let disposals = [Disposal {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 06 - 01),
+        quantity: amount("10"),
+        usd_proceeds: amount("1050"),
+        usd_reported_gain_loss: amount("50"),
+        source_file: String::new(),
+        account: String::new(),
+    }];
+    let error = match_disposals(&acquisitions, &disposals, |date| Ok((Decimal::ONE, date)));
+    assert!(matches!(error, Err(CgtError::InvalidQuantity { .. })));
+}
+
+#[test]
+fn disposals_without_shares_are_rejected() {
+    let acquisitions = [Acquisition {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 01 - 10),
+        quantity: amount("10"),
+        usd_cost: amount("1000"),
+    }];
+    let disposals = [Disposal {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 06 - 01),
+        quantity: amount("-10"),
+        usd_proceeds: amount("1050"),
+        usd_reported_gain_loss: amount("50"),
+        source_file: String::new(),
+        account: String::new(),
+    }];
+    let error = match_disposals(&acquisitions, &disposals, |date| Ok((Decimal::ONE, date)));
+    assert!(matches!(
+        error,
+        Err(CgtError::InvalidQuantity {
+            operation: "disposal",
+            ..
+        })
+    ));
+}
+
+#[test]
+fn currency_loss_is_disallowed_when_reacquired_within_four_weeks() {
+    let acquisition = |date| Acquisition {
+        symbol: "ACME".to_string(),
+        date,
+        quantity: amount("10"),
+        usd_cost: amount("1000"),
+    };
+    let disposals = [Disposal {
+        symbol: "ACME".to_string(),
+        date: date!(2023 - 06 - 01),
+        quantity: amount("10"),
+        usd_proceeds: amount("1050"),
+        usd_reported_gain_loss: amount("50"),
+        source_file: String::new(),
+        account: String::new(),
+    }];
+    let acquisitions = [
+        acquisition(date!(2022 - 01 - 10)),
+        acquisition(date!(2023 - 06 - 15)),
+    ];
+    let mut transactions = match_disposals(&acquisitions, &disposals, |date| {
+        let exr = if date.year() == 2022 { "1" } else { "1.2" };
+        Ok((amount(exr), date))
+    })
+    .unwrap();
+    // A gain in dollars but a loss of €125, as the dollar gained on the euro
+    assert_eq!(transactions[0].eur_loss(), Eur(amount("125")));
+
+    apply_four_week_rule(&mut transactions, &acquisitions);
+    let t = &transactions[0];
+    assert_eq!(t.eur_loss(), Eur::ZERO);
+    assert_eq!(t.usd_loss(), Decimal::ZERO);
+    assert!(t.four_week_rule_applied());
+}
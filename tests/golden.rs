@@ -0,0 +1,276 @@
+//! End-to-end checks of the reports of a known export, including against a golden
+//! file, with the exchange rates of a rates file so that the ECB is not queried.
+
+#![cfg(feature = "io")]
+
+use std::{fs, path::PathBuf};
+
+use capital_gain_tax_ireland::{
+    build_custom_period_report, build_pooled_report, build_report, build_report_for_periods,
+    compute_monthly_report, ecb_series_key, get_transactions, largest_gain_and_loss,
+    project_december, summarise_years, usd_gain_reconciliation, write_detail,
+    write_report_as_markdown, Eur, PaymentPeriod, ReadParams, TaxParams, Transaction,
+};
+use rust_decimal::Decimal;
+use time::macros::date;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn read_fixture() -> Vec<Transaction> {
+    let read_params = ReadParams {
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+    get_transactions(fixture("gains_and_losses.xlsx"), &read_params).unwrap()
+}
+
+#[test]
+fn report_matches_golden_file() {
+    let transactions = read_fixture();
+    let mut report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    // The golden file is shared with the checks of the rates fetched from the ECB
+    let rates_file = fixture("rates.csv").display().to_string();
+    assert_eq!(report.rate_sources, [rates_file]);
+    report.rate_sources = vec![ecb_series_key("USD", "EUR")];
+
+    let expected: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(fixture("expected_report.json")).unwrap())
+            .unwrap();
+    assert_eq!(serde_json::to_value(&report).unwrap(), expected);
+}
+
+#[test]
+fn single_payment_period_taxes_the_whole_year() {
+    let transactions = read_fixture();
+    let periods = [PaymentPeriod {
+        start: date!(2023 - 01 - 01),
+        end: date!(2023 - 12 - 31),
+        due_date: date!(2024 - 10 - 31),
+        label: "fiscal year",
+    }];
+    let report = build_report_for_periods(&transactions, &TaxParams::default(), &periods).unwrap();
+
+    assert_eq!(report.payment_periods.len(), 1);
+    let period = &report.payment_periods[0];
+    assert_eq!(period.eur_tax, report.period_tax_report.eur_tax);
+    assert_eq!(period.eur_allowance, report.period_tax_report.eur_allowance);
+}
+
+#[test]
+fn custom_period_reports_its_disposals_only() {
+    let transactions = read_fixture();
+    let params = TaxParams::default();
+    let report = build_report(&transactions, &params).unwrap();
+
+    let initial = build_custom_period_report(
+        &transactions,
+        &params,
+        (date!(2023 - 01 - 01), date!(2023 - 11 - 30)),
+    )
+    .unwrap();
+    assert_eq!(initial.transaction_count, 2);
+    assert_eq!(initial.eur_net_gain, report.payment_periods[0].eur_net_gain);
+
+    for period in [
+        (date!(2023 - 06 - 30), date!(2023 - 03 - 01)),
+        (date!(2023 - 12 - 01), date!(2024 - 01 - 31)),
+        (date!(2022 - 01 - 01), date!(2022 - 12 - 31)),
+    ] {
+        assert!(build_custom_period_report(&transactions, &params, period).is_err());
+    }
+}
+
+#[test]
+fn months_sum_to_the_year() {
+    let transactions = read_fixture();
+    let months = compute_monthly_report(&transactions).unwrap();
+    let year = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    let net_gain: Eur = months.iter().map(|m| m.eur_net_gain).sum();
+    let proceeds: Eur = months.iter().map(|m| m.eur_proceeds).sum();
+    let count: usize = months.iter().map(|m| m.transaction_count).sum();
+    assert_eq!(net_gain, year.period_tax_report.eur_net_gain);
+    assert_eq!(proceeds, year.period_tax_report.eur_proceeds);
+    assert_eq!(count, year.period_tax_report.transaction_count);
+    assert_eq!(months[0].transaction_count, 0);
+    assert!(months[0].eur_net_gain.is_zero());
+}
+
+#[test]
+fn largest_gain_and_loss_of_the_year() {
+    let transactions = read_fixture();
+    let (gain, loss) = largest_gain_and_loss(&transactions);
+    let gain = serde_json::to_value(gain.unwrap()).unwrap();
+    let loss = serde_json::to_value(loss.unwrap()).unwrap();
+    assert_eq!(gain["sell_date"], "2023-03-15");
+    assert_eq!(loss["sell_date"], "2023-06-20");
+
+    assert!(matches!(largest_gain_and_loss(&[]), (None, None)));
+}
+
+#[test]
+fn projecting_the_actual_december_gives_the_year() {
+    let transactions = read_fixture();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+    let (initial, december) = (&report.payment_periods[0], &report.payment_periods[1]);
+
+    let projection =
+        project_december(initial, december.eur_net_gain, TaxParams::default().rate).unwrap();
+    let year = &report.period_tax_report;
+    assert_eq!(projection.eur_net_gain, year.eur_net_gain);
+    assert_eq!(projection.eur_taxable_gain, year.eur_taxable_gain);
+    assert_eq!(projection.eur_tax, year.eur_tax);
+    assert_eq!(
+        projection.period,
+        Some((date!(2023 - 01 - 01), date!(2023 - 12 - 31)))
+    );
+}
+
+#[test]
+fn return_is_required_above_the_proceeds_threshold() {
+    let transactions = read_fixture();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+    assert!(report.return_required);
+
+    // The threshold applies to the proceeds, not to the gains
+    let params = TaxParams {
+        return_threshold_eur: report.period_tax_report.eur_proceeds,
+        ..TaxParams::default()
+    };
+    let report = build_report(&transactions, &params).unwrap();
+    assert!(!report.return_required);
+}
+
+#[test]
+fn lifetime_summary_sums_the_years() {
+    let transactions = read_fixture();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+    let summary = summarise_years(&[report]);
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    assert_eq!(summary.years, Some((2023, 2023)));
+    assert_eq!(summary.eur_proceeds, report.period_tax_report.eur_proceeds);
+    assert_eq!(summary.eur_net_gain, report.period_tax_report.eur_net_gain);
+    assert_eq!(summary.eur_tax, report.period_tax_report.eur_tax);
+    assert_eq!(summarise_years(&[]).years, None);
+}
+
+#[test]
+fn summary_line_formats_the_year() {
+    let report = build_report(&read_fixture(), &TaxParams::default()).unwrap();
+    assert_eq!(
+        report.summary_line(),
+        "FY2023: net gain €2,064.96, taxable €794.96, tax due €262.34 (return required)"
+    );
+}
+
+#[test]
+fn usd_gain_is_reconciled_with_the_statement() {
+    let reports = [build_report(&read_fixture(), &TaxParams::default()).unwrap()];
+    assert_eq!(
+        usd_gain_reconciliation(&reports, "2200".parse().unwrap()),
+        "Net gain (USD): $2,200.00, matches the expected $2,200.00"
+    );
+    assert_eq!(
+        usd_gain_reconciliation(&reports, "2350.5".parse().unwrap()),
+        "Net gain (USD): $2,200.00, differs by -$150.50 from the expected $2,350.50"
+    );
+}
+
+#[test]
+fn detail_is_written_to_any_writer() {
+    let transactions = read_fixture();
+    let mut detail = Vec::new();
+    write_detail(&transactions, &mut detail).unwrap();
+
+    let detail = String::from_utf8(detail).unwrap();
+    let lines: Vec<&str> = detail.lines().collect();
+    assert_eq!(lines.len(), 1 + transactions.len());
+    assert!(lines[0].starts_with("Sell Date,Symbol,"), "{}", lines[0]);
+    assert!(lines[1].starts_with("2023-03-15,ACME,"), "{}", lines[1]);
+}
+
+#[test]
+fn markdown_report_has_a_column_per_period() {
+    let transactions = read_fixture();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+    let mut markdown = Vec::new();
+    write_report_as_markdown(&report, &mut markdown).unwrap();
+    let markdown = String::from_utf8(markdown).unwrap();
+
+    assert!(markdown.starts_with("## Fiscal year 2023\n"));
+    assert!(markdown.contains("| | initial period | later period | entire fiscal year |\n"));
+    assert!(markdown.contains("| Net gain (EUR) | 1,232.24 | 832.72 | 2,064.96 |\n"));
+    assert!(markdown.contains("| Tax to pay (EUR) | 0.00 | 262.34 | 262.34 |\n"));
+}
+
+#[test]
+fn disposals_after_emigration_are_excluded() {
+    let read_params = ReadParams {
+        rates_file: Some(fixture("rates.csv")),
+        resident_until: Some(date!(2023 - 06 - 30)),
+        ..ReadParams::default()
+    };
+    let transactions = get_transactions(fixture("gains_and_losses.xlsx"), &read_params).unwrap();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    // The disposal of December is still in the detail, flagged as excluded
+    assert_eq!(transactions.len(), 3);
+    let excluded: Vec<bool> = transactions.iter().map(Transaction::excluded).collect();
+    assert_eq!(excluded, [false, false, true]);
+    assert_eq!(report.period_tax_report.transaction_count, 2);
+    assert_eq!(report.payment_periods[1].transaction_count, 0);
+}
+
+#[test]
+fn pooled_exemptions_shelter_the_gains_of_their_own_person_only() {
+    let mine = read_fixture();
+    let spouse = vec![Transaction::new(
+        date!(2023 - 05 - 10),
+        "100".parse().unwrap(),
+        "500".parse().unwrap(),
+        Decimal::ONE,
+    )];
+    let report = build_pooled_report(&[mine, spouse], &TaxParams::default()).unwrap();
+
+    // The €100 gain of the spouse is within their exemption, the rest of which cannot
+    // reduce the tax on the €2,064.96 of the fixture
+    let year = &report.period_tax_report;
+    assert_eq!(year.transaction_count, 4);
+    assert_eq!(
+        year.eur_net_gain.round_to_cents(),
+        Eur("2164.96".parse().unwrap())
+    );
+    assert_eq!(
+        year.eur_tax.round_to_cents(),
+        Eur("262.34".parse().unwrap())
+    );
+}
+
+#[test]
+fn pooled_losses_reduce_the_gains_of_the_other_person() {
+    let mine = read_fixture();
+    let spouse = vec![Transaction::new(
+        date!(2023 - 05 - 10),
+        "-500".parse().unwrap(),
+        "500".parse().unwrap(),
+        Decimal::ONE,
+    )];
+    let report = build_pooled_report(&[mine, spouse], &TaxParams::default()).unwrap();
+
+    // The €500 loss of the spouse reduces the €794.96 taxed of the fixture to €294.96,
+    // and nothing is left to carry forward
+    let year = &report.period_tax_report;
+    assert_eq!(
+        year.eur_taxable_gain.round_to_cents(),
+        Eur("294.96".parse().unwrap())
+    );
+    assert_eq!(year.eur_tax.round_to_cents(), Eur("97.34".parse().unwrap()));
+    assert_eq!(report.loss_carried_forward_in, Eur::ZERO);
+    assert_eq!(report.loss_carried_forward_out, Eur::ZERO);
+}
@@ -0,0 +1,66 @@
+use capital_gain_tax_ireland::{format_money, parse_money, Eur, RoundMode};
+use rust_decimal::Decimal;
+
+fn amount(text: &str) -> Option<Decimal> {
+    text.parse().ok()
+}
+
+#[test]
+fn currency_symbols_are_ignored() {
+    assert_eq!(parse_money("$1234.56"), amount("1234.56"));
+    assert_eq!(parse_money(" -$12.00 "), amount("-12.00"));
+    assert_eq!(parse_money("$-12"), amount("-12"));
+}
+
+#[test]
+fn thousands_separators_are_ignored() {
+    assert_eq!(parse_money("$1,234.56"), amount("1234.56"));
+    assert_eq!(parse_money("1,234,567"), amount("1234567"));
+}
+
+#[test]
+fn parentheses_denote_negative_amounts() {
+    assert_eq!(parse_money("(123.45)"), amount("-123.45"));
+    assert_eq!(parse_money("($1,234.56)"), amount("-1234.56"));
+}
+
+#[test]
+fn other_texts_are_rejected() {
+    for text in ["", "$", "N/A", "(12", "1.2.3"] {
+        assert_eq!(parse_money(text), None, "{:?}", text);
+    }
+}
+
+#[test]
+fn halves_are_rounded_with_the_round_mode() {
+    let eur = |text: &str| Eur(text.parse().unwrap());
+    assert_eq!(eur("0.125").round_to_cents(), eur("0.13"));
+    assert_eq!(
+        eur("0.125").round_to_cents_with(RoundMode::HalfEven),
+        eur("0.12")
+    );
+    assert_eq!(
+        eur("0.135").round_to_cents_with(RoundMode::HalfEven),
+        eur("0.14")
+    );
+    assert_eq!(
+        eur("-0.125").round_to_cents_with(RoundMode::HalfUp),
+        eur("-0.13")
+    );
+}
+
+#[test]
+fn printed_amounts_have_thousands_separators() {
+    let money = |text: &str| format_money(text.parse().unwrap());
+    assert_eq!(money("123456.785"), "123,456.79");
+    assert_eq!(money("-1234567.8"), "-1,234,567.80");
+    assert_eq!(money("999.999"), "1,000.00");
+    assert_eq!(money("12"), "12.00");
+    assert_eq!(money("-0.001"), "0.00");
+}
+
+#[test]
+#[should_panic(expected = "not positive")]
+fn a_zero_exchange_rate_is_rejected() {
+    Eur::convert(Decimal::ONE, Decimal::ZERO);
+}
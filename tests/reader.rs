@@ -0,0 +1,149 @@
+//! Checks of the reading of transactions from CSV that is not in a file, of the rows
+//! that cannot be parsed, and of the sales read twice.
+
+#![cfg(feature = "io")]
+
+use std::{fs, path::PathBuf};
+
+use capital_gain_tax_ireland::{
+    build_report, build_report_from_iter, get_transactions_from_csv,
+    get_transactions_from_files_by_year, get_transactions_from_reader,
+    get_transactions_with_skipped_rows, stream_transactions_from_csv, DateFormat, Eur, ReadParams,
+    TaxParams, READER_SOURCE,
+};
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+#[test]
+fn reader_gives_the_transactions_of_the_file() {
+    let params = ReadParams {
+        date_format: DateFormat::DayMonthYear,
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+    let file = fixture("european_dates.csv");
+    let from_file = get_transactions_from_csv(&file, &params).unwrap();
+    let from_reader =
+        get_transactions_from_reader(fs::read(&file).unwrap().as_slice(), &params).unwrap();
+
+    assert_eq!(from_reader.len(), 3);
+    for (from_reader, from_file) in from_reader.iter().zip(&from_file) {
+        assert_eq!(from_reader.sell_date(), from_file.sell_date());
+        assert_eq!(from_reader.eur_gain(), from_file.eur_gain());
+        assert_eq!(from_reader.eur_loss(), from_file.eur_loss());
+        assert_eq!(from_reader.source_file(), READER_SOURCE);
+    }
+}
+
+#[test]
+fn gains_are_computed_from_the_price_of_the_shares() {
+    let sales = "Record Type,Date Sold,Quantity,Sale Price,Cost Basis\n\
+        Sell,03/15/2023,10,$105.98,\"$1,000.00\"\n";
+    let params = ReadParams {
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+    let transactions = get_transactions_from_reader(sales.as_bytes(), &params).unwrap();
+
+    let t = &transactions[0];
+    assert_eq!(t.usd_proceeds(), "1059.80".parse().unwrap());
+    assert_eq!(t.usd_gain(), "59.80".parse().unwrap());
+    assert_eq!(t.eur_proceeds(), Eur("1000".parse().unwrap()));
+}
+
+#[test]
+fn rows_that_fail_to_parse_are_skipped_unless_strict() {
+    let file_path = std::env::temp_dir().join("cgt_invalid_row.csv");
+    fs::write(
+        &file_path,
+        "Record Type,Date Sold,Adjusted Gain/Loss,Total Proceeds\n\
+        Sell,03/15/2023,100,1000\n\
+        Sell,06/20/2023,n/a,1000\n\
+        Sell,12/05/2023,50,500\n",
+    )
+    .unwrap();
+    let params = ReadParams {
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+
+    let (by_year, skipped) = get_transactions_with_skipped_rows(&file_path, &params).unwrap();
+    assert_eq!(by_year[&2023].len(), 2);
+    assert_eq!(skipped.len(), 1);
+    assert_eq!(skipped[0].row, 3);
+    assert!(
+        skipped[0].error.contains("gain/loss"),
+        "{}",
+        skipped[0].error
+    );
+
+    let strict = ReadParams {
+        strict: true,
+        ..params
+    };
+    assert!(get_transactions_with_skipped_rows(&file_path, &strict).is_err());
+}
+
+#[test]
+fn duplicate_sales_are_removed_with_dedupe() {
+    let file = fixture("european_dates.csv");
+    let files = [&file, &file];
+    let params = ReadParams {
+        date_format: DateFormat::DayMonthYear,
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+    let kept = get_transactions_from_files_by_year(&files, &params).unwrap();
+    assert_eq!(kept[&2023].len(), 6);
+
+    let dedupe = ReadParams {
+        dedupe: true,
+        ..params
+    };
+    let deduped = get_transactions_from_files_by_year(&files, &dedupe).unwrap();
+    assert_eq!(deduped[&2023].len(), 3);
+}
+
+#[test]
+fn streamed_transactions_give_the_same_report() {
+    let params = ReadParams {
+        date_format: DateFormat::DayMonthYear,
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+    let file = fixture("european_dates.csv");
+    let transactions = get_transactions_from_csv(&file, &params).unwrap();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+    let stream = stream_transactions_from_csv(&file, &params).unwrap();
+    let streamed_report = build_report_from_iter(stream, &TaxParams::default()).unwrap();
+
+    assert_eq!(
+        serde_json::to_value(&streamed_report).unwrap(),
+        serde_json::to_value(&report).unwrap()
+    );
+}
+
+#[test]
+fn short_round_trip_is_counted_once() {
+    let params = ReadParams {
+        rates_file: Some(fixture("rates.csv")),
+        ..ReadParams::default()
+    };
+    // The short sale opens on 15 March and is covered on 20 June for a $100 gain
+    let sales = "Record Type,Date Sold,Adjusted Gain/Loss,Total Proceeds\n\
+        Short Sell,03/15/2023,,1000\n\
+        Buy to Cover,06/20/2023,100,-900\n\
+        Sell,06/20/2023,50,500\n";
+    let transactions = get_transactions_from_reader(sales.as_bytes(), &params).unwrap();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    assert_eq!(transactions.len(), 2);
+    let year = &report.period_tax_report;
+    assert_eq!(year.usd_net_gain, "150".parse().unwrap());
+    // The cost of covering is not deducted from the proceeds of the sale
+    assert_eq!(year.usd_proceeds, "500".parse().unwrap());
+}
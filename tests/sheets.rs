@@ -0,0 +1,76 @@
+//! Checks of the listing of the sheets of a workbook, and of the suggestion of a sheet
+//! when the requested one is missing.
+
+#![cfg(feature = "io")]
+
+use std::path::PathBuf;
+
+use capital_gain_tax_ireland::{
+    build_report, check_file, get_transactions, list_sheets, CgtError, ReadParams, TaxParams,
+};
+use rust_xlsxwriter::Workbook;
+
+/// Writes a workbook with empty sheets of the given names, and returns its path.
+fn workbook_with_sheets(file_name: &str, sheet_names: &[&str]) -> PathBuf {
+    let file_path = std::env::temp_dir().join(file_name);
+    let mut workbook = Workbook::new();
+    for name in sheet_names {
+        workbook.add_worksheet().set_name(*name).unwrap();
+    }
+    workbook.save(&file_path).unwrap();
+    file_path
+}
+
+#[test]
+fn sheets_are_listed_in_order() {
+    let file_path = workbook_with_sheets("cgt_listed_sheets.xlsx", &["Summary", "G&L_Expanded"]);
+    assert_eq!(list_sheets(file_path).unwrap(), ["Summary", "G&L_Expanded"]);
+}
+
+#[test]
+fn missing_sheet_suggests_the_closest_one() {
+    let file_path = workbook_with_sheets("cgt_missing_sheet.xlsx", &["Summary", "G&L_Expanded"]);
+    let read_params = ReadParams {
+        sheet: "g&l expanded".to_string(),
+        ..ReadParams::default()
+    };
+    let error = check_file(&file_path, &read_params).unwrap_err();
+    assert!(
+        matches!(&error, CgtError::MissingSheet { closest: Some(closest), .. } if closest == "G&L_Expanded"),
+        "{:?}",
+        error
+    );
+
+    let read_params = ReadParams {
+        sheet: "Trades".to_string(),
+        ..ReadParams::default()
+    };
+    let error = check_file(&file_path, &read_params).unwrap_err();
+    assert!(
+        matches!(&error, CgtError::MissingSheet { closest: None, .. }),
+        "{:?}",
+        error
+    );
+}
+
+#[test]
+fn sheet_without_rows_has_no_disposals() {
+    let file_path = std::env::temp_dir().join("cgt_header_only.xlsx");
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet().set_name("G&L_Expanded").unwrap();
+    for (col, header) in (0..).zip([
+        "Record Type",
+        "Date Sold",
+        "Adjusted Gain/Loss",
+        "Total Proceeds",
+    ]) {
+        sheet.write_string(0, col, header).unwrap();
+    }
+    workbook.save(&file_path).unwrap();
+
+    // Not a report of zeros dated year 0
+    let transactions = get_transactions(&file_path, &ReadParams::default()).unwrap();
+    assert!(transactions.is_empty());
+    let error = build_report(&transactions, &TaxParams::default()).unwrap_err();
+    assert!(matches!(error, CgtError::NoDisposals), "{:?}", error);
+}
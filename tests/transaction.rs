@@ -0,0 +1,389 @@
+//! Checks of the reports of transactions built without reading any file.
+
+use std::collections::{BTreeMap, HashMap};
+
+use capital_gain_tax_ireland::{
+    build_report, build_reports_by_year, convert_sales_with_provider, convert_sales_with_rates,
+    statutory_rate_change, unique_sell_dates, CgtError, Eur, ReadParams, Sale, TaxParams,
+    Transaction,
+};
+use rust_decimal::Decimal;
+use time::macros::date;
+
+fn amount(text: &str) -> Decimal {
+    text.parse().unwrap()
+}
+
+#[test]
+fn transactions_are_converted_at_their_rate() {
+    let t = Transaction::new(
+        date!(2023 - 03 - 15),
+        amount("-100"),
+        amount("500"),
+        amount("1.25"),
+    );
+    assert_eq!(t.sell_date(), date!(2023 - 03 - 15));
+    assert_eq!(t.exr_date(), t.sell_date());
+    assert_eq!(t.usd_gain(), Decimal::ZERO);
+    assert_eq!(t.usd_loss(), amount("100"));
+    assert_eq!(t.eur_gain(), Eur::ZERO);
+    assert_eq!(t.eur_loss(), Eur(amount("80")));
+    assert_eq!(t.eur_proceeds(), Eur(amount("400")));
+    assert!(!t.excluded());
+}
+
+#[test]
+fn reports_are_built_from_transactions() {
+    let transactions = [
+        Transaction::new(
+            date!(2023 - 03 - 15),
+            amount("3000"),
+            amount("6000"),
+            amount("1.5"),
+        ),
+        Transaction::new(
+            date!(2023 - 12 - 05),
+            amount("-500"),
+            amount("1000"),
+            amount("1"),
+        ),
+    ];
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    let year = &report.period_tax_report;
+    assert_eq!(year.eur_net_gain, Eur(amount("1500")));
+    assert_eq!(year.eur_taxable_gain, Eur(amount("230")));
+    assert_eq!(year.eur_tax, Eur(amount("75.90")));
+    assert_eq!(report.payment_periods[0].eur_tax, Eur(amount("240.90")));
+}
+
+#[test]
+fn december_losses_do_not_undo_the_initial_payment() {
+    let transactions = [
+        Transaction::new(
+            date!(2023 - 11 - 20),
+            amount("10000"),
+            amount("20000"),
+            amount("1"),
+        ),
+        Transaction::new(
+            date!(2023 - 12 - 05),
+            amount("-8000"),
+            amount("2000"),
+            amount("1"),
+        ),
+    ];
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    // The tax of Jan-Nov is due on its own net gain, December's loss notwithstanding
+    let (initial, december) = (&report.payment_periods[0], &report.payment_periods[1]);
+    assert_eq!(initial.eur_tax, Eur(amount("2880.90")));
+    assert_eq!(december.eur_tax, Eur::ZERO);
+    // The loss only reduces the tax of the year, settled with the annual return
+    assert_eq!(report.period_tax_report.eur_tax, Eur(amount("240.90")));
+    assert_eq!(report.eur_tax_adjustment, Eur(amount("-2640.00")));
+}
+
+#[test]
+fn other_losses_do_not_reduce_gains_on_development_land() {
+    let transactions = [
+        Transaction::new(
+            date!(2023 - 03 - 15),
+            amount("5000"),
+            amount("20000"),
+            amount("1"),
+        )
+        .with_development_land(true),
+        Transaction::new(
+            date!(2023 - 04 - 10),
+            amount("-2000"),
+            amount("3000"),
+            amount("1"),
+        ),
+    ];
+    let params = TaxParams {
+        development_land_rate: amount("0.40"),
+        ..TaxParams::default()
+    };
+    let report = build_report(&transactions, &params).unwrap();
+
+    // The exemption goes to the development land gain, as there is no other gain
+    let year = &report.period_tax_report;
+    assert_eq!(year.eur_development_land_taxable_gain, Eur(amount("3730")));
+    assert_eq!(year.eur_development_land_tax, Eur(amount("1492.00")));
+    assert_eq!(year.eur_taxable_gain, Eur(amount("3730")));
+    assert_eq!(year.eur_tax, Eur(amount("1492.00")));
+
+    // Nor do the losses carried forward, which are left for the next year
+    let params = TaxParams {
+        loss_carried_forward_eur: Eur(amount("1000")),
+        ..params
+    };
+    let report = build_report(&transactions, &params).unwrap();
+    let year = &report.period_tax_report;
+    assert_eq!(year.eur_development_land_taxable_gain, Eur(amount("3730")));
+    assert_eq!(year.eur_tax, Eur(amount("1492.00")));
+    assert_eq!(report.loss_carried_forward_out, Eur(amount("3000")));
+}
+
+#[test]
+fn exemption_used_elsewhere_is_deducted_from_the_exemption() {
+    let transactions = [Transaction::new(
+        date!(2023 - 03 - 15),
+        amount("2000"),
+        amount("5000"),
+        amount("1"),
+    )];
+    let report = |exemption_used: &str| {
+        let params = TaxParams {
+            exemption_used_eur: Eur(amount(exemption_used)),
+            ..TaxParams::default()
+        };
+        build_report(&transactions, &params).unwrap()
+    };
+
+    let partly_used = report("1000");
+    assert_eq!(partly_used.eur_exemption_used, Eur(amount("1000")));
+    assert_eq!(partly_used.eur_exemption, Eur(amount("270")));
+    assert_eq!(
+        partly_used.period_tax_report.eur_taxable_gain,
+        Eur(amount("1730"))
+    );
+
+    // The remaining exemption is never negative
+    let overused = report("5000");
+    assert_eq!(overused.eur_exemption, Eur::ZERO);
+    assert_eq!(
+        overused.period_tax_report.eur_taxable_gain,
+        Eur(amount("2000"))
+    );
+}
+
+#[test]
+fn older_years_have_their_statutory_rate() {
+    assert_eq!(TaxParams::for_year(2024).rate, TaxParams::default().rate);
+    assert_eq!(TaxParams::for_year(2012).rate, amount("0.30"));
+    assert_eq!(TaxParams::for_year(2007).rate, amount("0.20"));
+
+    let transactions = [Transaction::new(
+        date!(2010 - 06 - 15),
+        amount("2270"),
+        amount("5000"),
+        amount("1"),
+    )];
+    let params = TaxParams::for_year(2010);
+    let report = build_report(&transactions, &params).unwrap();
+    assert_eq!(params.exemption_eur, Eur(amount("1270")));
+    assert_eq!(report.period_tax_report.eur_tax, Eur(amount("250.00")));
+}
+
+#[test]
+fn disposals_have_the_statutory_rate_of_their_date() {
+    assert_eq!(
+        TaxParams::for_date(date!(2012 - 12 - 05)).rate,
+        amount("0.30")
+    );
+    assert_eq!(
+        TaxParams::for_date(date!(2012 - 12 - 06)).rate,
+        amount("0.33")
+    );
+    assert_eq!(
+        TaxParams::for_date(date!(2008 - 10 - 15)).rate,
+        amount("0.22")
+    );
+
+    let t = |date| Transaction::new(date, amount("100"), amount("500"), amount("1"));
+    let december = [t(date!(2012 - 12 - 10)), t(date!(2012 - 12 - 20))];
+    assert_eq!(statutory_rate_change(&december), None);
+    assert_eq!(
+        TaxParams::for_disposals(2012, &december).rate,
+        amount("0.33")
+    );
+
+    // Across the change, the rate of most of the year applies
+    let both = [t(date!(2012 - 03 - 10)), t(date!(2012 - 12 - 20))];
+    assert_eq!(statutory_rate_change(&both), Some(date!(2012 - 12 - 06)));
+    assert_eq!(TaxParams::for_disposals(2012, &both).rate, amount("0.30"));
+}
+
+fn sale(sell_date: time::Date, gain_loss: &str, proceeds: &str) -> Sale {
+    Sale {
+        sell_date,
+        gain_loss: amount(gain_loss),
+        usd_proceeds: amount(proceeds),
+        symbol: "ACME".to_string(),
+        source_file: String::new(),
+        cost_basis: None,
+        account: String::new(),
+        fees: Decimal::ZERO,
+        currency: None,
+    }
+}
+
+#[test]
+fn sales_are_converted_with_the_rates_of_the_provider() {
+    let mut rates = HashMap::from([
+        (date!(2023 - 03 - 15), amount("1.25")),
+        (date!(2023 - 06 - 20), amount("1.10")),
+    ]);
+    let sales = vec![
+        sale(date!(2023 - 03 - 15), "125", "1250"),
+        sale(date!(2023 - 06 - 20), "-55", "550"),
+    ];
+    let transactions =
+        convert_sales_with_provider(sales, &mut rates, &ReadParams::default()).unwrap();
+
+    assert_eq!(transactions[0].eur_gain(), Eur(amount("100")));
+    assert_eq!(transactions[1].eur_loss(), Eur(amount("50")));
+    assert_eq!(transactions[1].exr_date(), date!(2023 - 06 - 20));
+
+    let sales = vec![sale(date!(2023 - 06 - 21), "10", "100")];
+    let error = convert_sales_with_provider(sales, &mut rates, &ReadParams::default());
+    assert!(matches!(
+        error,
+        Err(CgtError::ExchangeRateUnavailable(day)) if day == date!(2023 - 06 - 21)
+    ));
+
+    rates.insert(date!(2023 - 06 - 22), Decimal::ZERO);
+    let sales = vec![sale(date!(2023 - 06 - 22), "10", "100")];
+    let error = convert_sales_with_provider(sales, &mut rates, &ReadParams::default());
+    assert!(matches!(
+        error,
+        Err(CgtError::InvalidExchangeRate(day, _)) if day == date!(2023 - 06 - 22)
+    ));
+}
+
+#[test]
+fn amounts_are_only_converted_to_euros() {
+    let mut rates = HashMap::from([(date!(2023 - 03 - 15), amount("0.85"))]);
+    let params = ReadParams {
+        to_currency: "GBP".to_string(),
+        ..ReadParams::default()
+    };
+    let sales = vec![sale(date!(2023 - 03 - 15), "125", "1250")];
+    let error = convert_sales_with_provider(sales, &mut rates, &params);
+    assert!(matches!(
+        error,
+        Err(CgtError::UnsupportedTargetCurrency(currency)) if currency == "GBP"
+    ));
+}
+
+#[test]
+fn given_rates_must_be_positive() {
+    let rates = BTreeMap::from([
+        (date!(2023 - 03 - 15), amount("1.25")),
+        (date!(2023 - 06 - 20), Decimal::ZERO),
+    ]);
+    let sales = vec![sale(date!(2023 - 03 - 16), "125", "1250")];
+    let transactions = convert_sales_with_rates(sales, &rates, &ReadParams::default()).unwrap();
+    assert_eq!(transactions[0].eur_gain(), Eur(amount("100")));
+
+    let sales = vec![sale(date!(2023 - 06 - 20), "10", "100")];
+    let error = convert_sales_with_rates(sales, &rates, &ReadParams::default());
+    assert!(matches!(
+        error,
+        Err(CgtError::InvalidExchangeRate(day, _)) if day == date!(2023 - 06 - 20)
+    ));
+}
+
+#[test]
+fn sell_dates_are_listed_once_in_order() {
+    let t = |date| Transaction::new(date, amount("10"), amount("100"), amount("1.1"));
+    let transactions = [
+        t(date!(2023 - 06 - 20)),
+        t(date!(2023 - 03 - 15)),
+        t(date!(2023 - 06 - 20)),
+    ];
+    let dates: Vec<_> = unique_sell_dates(&transactions).into_iter().collect();
+    assert_eq!(dates, [date!(2023 - 03 - 15), date!(2023 - 06 - 20)]);
+}
+
+#[test]
+fn reported_totals_are_the_sums_of_the_rounded_amounts() {
+    // Each gain is €0.333… before rounding to the cent: the rounded gains sum to €9.90
+    // whereas the unrounded ones would be reported as €10.00
+    let mut rates = HashMap::from([(date!(2023 - 03 - 15), amount("3"))]);
+    // Different proceeds, not to be taken for duplicates
+    let sales = (10..40)
+        .map(|proceeds| sale(date!(2023 - 03 - 15), "1", &proceeds.to_string()))
+        .collect();
+    let transactions =
+        convert_sales_with_provider(sales, &mut rates, &ReadParams::default()).unwrap();
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    assert!(transactions
+        .iter()
+        .all(|t| t.eur_gain() == Eur(amount("0.33"))));
+    let total: Eur = transactions.iter().map(Transaction::eur_gain).sum();
+    assert_eq!(report.period_tax_report.eur_gain, total);
+    assert_eq!(report.period_tax_report.eur_gain, Eur(amount("9.90")));
+}
+
+#[test]
+fn taxable_gains_of_the_periods_add_up_to_the_year() {
+    // Gains in both periods: without a loss in December, the exemption left by the
+    // initial period is what the later period can use
+    let transactions = [
+        Transaction::new(
+            date!(2023 - 03 - 15),
+            amount("1000"),
+            amount("5000"),
+            Decimal::ONE,
+        ),
+        Transaction::new(
+            date!(2023 - 07 - 01),
+            amount("-200"),
+            amount("800"),
+            Decimal::ONE,
+        ),
+        Transaction::new(
+            date!(2023 - 12 - 05),
+            amount("800"),
+            amount("4000"),
+            Decimal::ONE,
+        ),
+    ];
+    let report = build_report(&transactions, &TaxParams::default()).unwrap();
+
+    let periods: Eur = report
+        .payment_periods
+        .iter()
+        .map(|p| p.eur_taxable_gain)
+        .sum();
+    assert_eq!(periods, report.period_tax_report.eur_taxable_gain);
+    assert_eq!(periods, Eur(amount("330")));
+}
+
+#[test]
+fn carried_loss_is_used_up_by_the_first_years() {
+    let transactions = [
+        Transaction::new(
+            date!(2022 - 05 - 10),
+            amount("4270"),
+            amount("9000"),
+            Decimal::ONE,
+        ),
+        Transaction::new(
+            date!(2023 - 05 - 10),
+            amount("4270"),
+            amount("9000"),
+            Decimal::ONE,
+        ),
+    ];
+    let params = TaxParams {
+        loss_carried_forward_eur: Eur(amount("5000")),
+        ..TaxParams::default()
+    };
+    let reports = build_reports_by_year(&transactions, &params).unwrap();
+
+    // The losses are deducted before the exemption: 2022 uses €4,270 of them, and 2023
+    // only has the €730 left
+    assert_eq!(reports[0].period_tax_report.eur_taxable_gain, Eur::ZERO);
+    assert_eq!(reports[0].loss_carried_forward_out, Eur(amount("730")));
+    assert_eq!(reports[1].loss_carried_forward_in, Eur(amount("730")));
+    assert_eq!(reports[1].loss_carried_forward_out, Eur::ZERO);
+    assert_eq!(
+        reports[1].period_tax_report.eur_taxable_gain,
+        Eur(amount("2270"))
+    );
+}